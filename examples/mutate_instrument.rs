@@ -20,9 +20,9 @@ use std::io::{self, Read};
 use crossbeam::queue::ArrayQueue;
 use music_tools::synth::{Instrument, Instrumentation, Note, Voice};
 use music_tools::synth::simple_instruments::{ads, sr, Function, DAGVoice};
+use music_tools::random::{fresh_seed, Seeded};
 use music_tools::{Pitch, Scale};
 use portaudio as pa;
-use rand::prelude::*;
 use std::sync::Arc;
 
 type AudioSample = f32;
@@ -32,16 +32,6 @@ const FRAMES: u32 = 32;
 const SAMPLE_HZ: f64 = 48_000.0;
 
 
-fn mutate(parent: &DAGVoice) -> DAGVoice {
-    let mut rng = thread_rng();
-    let mut child = parent.clone();
-    for s in &mut child.initial_state {
-        *s *= rng.gen_range(0.8, 1.2);
-    }
-    child
-}
-
-
 fn main() -> Result<(), pa::Error> {
     let mut voice = DAGVoice::new(1.0);
     let mut instrumentation = Instrumentation::new();
@@ -108,8 +98,10 @@ fn main() -> Result<(), pa::Error> {
                 regenerate = true;
             }
             if regenerate {
-                voice = mutate(&voice);
-                term.write_line(&serde_json::to_string(&voice).unwrap());
+                // Printed with its seed so the mutation can be replayed
+                let child = Seeded::generate(fresh_seed(), |rng| voice.mutate(rng, 0.2));
+                term.write_line(&serde_json::to_string(&child).unwrap());
+                voice = child.value;
                 instrumentation = Instrumentation::new();
                 {
                     let voice = voice.clone();
@@ -132,6 +124,7 @@ fn main() -> Result<(), pa::Error> {
                         onset: clock,
                         duration: 0.4,
                         amplitude: 1.0,
+                        parameters: vec![],
                     };
                     clock += 0.5;
 
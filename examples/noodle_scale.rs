@@ -5,8 +5,10 @@ extern crate portaudio;
 extern crate rand;
 
 use crossbeam::queue::ArrayQueue;
+use music_tools::random::{fresh_seed, rng_from_seed};
 use music_tools::synth::simple_instruments::AdditiveBell;
-use music_tools::synth::{Instrument, Instrumentation, Note};
+use music_tools::synth::{Instrument, Instrumentation};
+use music_tools::tempo::{BeatNote, TempoMap};
 use music_tools::{Pitch, Scale};
 use portaudio as pa;
 use rand::prelude::*;
@@ -19,6 +21,14 @@ const FRAMES: u32 = 32;
 const SAMPLE_HZ: f64 = 48_000.0;
 
 fn main() -> Result<(), pa::Error> {
+    // Pass a previously printed seed to hear the same melody again
+    let seed = std::env::args()
+        .nth(1)
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(fresh_seed);
+    println!("seed: {}", seed);
+    let mut rng = rng_from_seed(seed);
+
     let mut instrumentation = Instrumentation::new();
     instrumentation.add_instrument(
         0,
@@ -53,26 +63,26 @@ fn main() -> Result<(), pa::Error> {
     let major_scale = Scale::new(&[whole, whole, semi, whole, whole, whole, semi]);
     let tonic = Pitch(220.0);
     let mut degree = 0;
+    let tempo = TempoMap::new(200.0);
 
     loop {
         let len = sample_buffer.len();
         if len < SAMPLE_HZ as usize {
             if instrumentation.exhausted() {
                 instrumentation.reset();
-                let mut clock = 0.0;
-                for _ in 0..100 {
-                    let note = Note {
+                for beat in 0..100 {
+                    let note = BeatNote {
                         instrument: 0,
                         pitch: major_scale.pitch(&tonic, degree),
-                        onset: clock,
-                        duration: 0.2,
+                        onset: f64::from(beat),
+                        duration: 2.0 / 3.0,
                         amplitude: 1.0,
+                        parameters: vec![],
                     };
-                    clock += 0.3;
 
-                    degree = (degree + thread_rng().gen_range(-1, 2)).max(-7).min(14);
+                    degree = (degree + rng.gen_range(-1, 2)).max(-7).min(14);
 
-                    instrumentation.schedule_note(&note);
+                    instrumentation.schedule_beat_note(&note, &tempo);
                 }
             }
             while !sample_buffer.is_full() {
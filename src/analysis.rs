@@ -0,0 +1,347 @@
+use std::cmp::Ordering;
+
+use super::chord::{ChordSymbol, Quality};
+use super::synth::Note;
+use super::{Key, Pitch};
+
+// Onsets and releases closer than this, in seconds, count as simultaneous.
+const EPSILON: f64 = 1e-6;
+
+// The chords segments are matched against, as (quality, seventh) pairs.
+const TEMPLATES: [(Quality, Option<i32>); 9] = [
+    (Quality::Major, None),
+    (Quality::Minor, None),
+    (Quality::Diminished, None),
+    (Quality::Augmented, None),
+    (Quality::Major, Some(10)),
+    (Quality::Major, Some(11)),
+    (Quality::Minor, Some(10)),
+    (Quality::Diminished, Some(10)),
+    (Quality::Diminished, Some(9)),
+];
+
+/// A stretch of music heard as a single harmony.
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub onset: f64,
+    pub duration: f64,
+    /// Indices of the notes sounding at any point in the segment.
+    pub notes: Vec<usize>,
+    pub symbol: ChordSymbol,
+    /// `None` when the key can't name the chord, see `Key::numeral`.
+    pub numeral: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cadence {
+    /// V or V7 to I with both chords in root position and the tonic on top.
+    PerfectAuthentic,
+    /// Any other dominant or leading tone chord to tonic.
+    ImperfectAuthentic,
+    /// Ending a phrase on the dominant.
+    Half,
+    /// IV to I.
+    Plagal,
+    /// V to vi.
+    Deceptive,
+}
+
+/// How a note that doesn't belong to the harmony around it moves in and
+/// out of its line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NonChordTone {
+    /// Stepwise between two notes in the same direction.
+    Passing,
+    /// Steps away and back again.
+    Neighbour,
+    /// Held or repeated from the previous harmony, then resolved by step.
+    Suspension,
+    /// Leapt to and left by step.
+    Appoggiatura,
+    /// Stepped to and left by leap.
+    Escape,
+    /// Arrives early, repeated in the next note.
+    Anticipation,
+    /// Sustained through several changes of harmony.
+    Pedal,
+    Other,
+}
+
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub segments: Vec<Segment>,
+    /// The index of the segment each cadence arrives on.
+    pub cadences: Vec<(usize, Cadence)>,
+    /// Indices of notes outside the harmony of a segment they sound in.
+    pub non_chord_tones: Vec<(usize, NonChordTone)>,
+}
+
+/// Finds the harmonies of `notes` and names them in `key`. The notes are
+/// cut wherever one starts or stops and each slice joins the segment before
+/// it while the segment's chord explains both at least as well as separate
+/// chords do, scoring chords by how long their tones sound against how long
+/// other tones sound and how many of their own are missing. Rests separate
+/// segments and the lowest note at the start of a segment is taken as its
+/// bass. Pitches are read as their nearest 12-TET pitch class.
+///
+/// Lines for non-chord tones are followed within each instrument, taking
+/// the nearest pitch when several notes start or stop together.
+pub fn analyse(notes: &[Note], key: &Key) -> Analysis {
+    let mut times: Vec<f64> = notes
+        .iter()
+        .flat_map(|note| vec![note.onset, note.onset + note.duration])
+        .collect();
+    times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    times.dedup_by(|b, a| *b - *a < EPSILON);
+
+    let mut segments: Vec<Segment> = vec![];
+    let mut current: Option<(f64, f64)> = None;
+    for slice in times.windows(2) {
+        let (start, end) = (slice[0], slice[1]);
+        if sounding(notes, start, end).is_empty() {
+            segments.extend(current.take().map(|(s, e)| segment(notes, key, s, e)));
+            continue;
+        }
+        current = match current {
+            Some((s, e)) => {
+                let (merged, merged_score) = best_chord(notes, s, end);
+                let (before, before_score) = best_chord(notes, s, e);
+                let (_, slice_score) = best_chord(notes, start, end);
+                if merged == before && merged_score >= before_score + slice_score - EPSILON {
+                    Some((s, end))
+                } else {
+                    segments.push(segment(notes, key, s, e));
+                    Some((start, end))
+                }
+            }
+            None => Some((start, end)),
+        };
+    }
+    segments.extend(current.map(|(s, e)| segment(notes, key, s, e)));
+
+    let cadences = cadences(&segments, notes, key);
+    let non_chord_tones = non_chord_tones(&segments, notes);
+    Analysis {
+        segments,
+        cadences,
+        non_chord_tones,
+    }
+}
+
+fn class(pitch: &Pitch) -> i32 {
+    pitch.nearest_midi_note().0.rem_euclid(12)
+}
+
+fn sounding(notes: &[Note], start: f64, end: f64) -> Vec<usize> {
+    (0..notes.len())
+        .filter(|i| {
+            let note = &notes[*i];
+            note.onset < end - EPSILON && note.onset + note.duration > start + EPSILON
+        })
+        .collect()
+}
+
+// The chord that best explains the notes between `start` and `end`, with
+// its score.
+fn best_chord(notes: &[Note], start: f64, end: f64) -> (ChordSymbol, f64) {
+    let mut weights = [0.0; 12];
+    for i in sounding(notes, start, end) {
+        let note = &notes[i];
+        let overlap = (note.onset + note.duration).min(end) - note.onset.max(start);
+        weights[class(&note.pitch) as usize] += overlap;
+    }
+    let total: f64 = weights.iter().sum();
+    let mut best: Option<(ChordSymbol, f64, f64)> = None;
+    for root in 0..12 {
+        for (quality, seventh) in TEMPLATES.iter() {
+            let mut symbol = ChordSymbol::new(root, *quality);
+            symbol.seventh = *seventh;
+            let classes: Vec<usize> = symbol
+                .tones()
+                .iter()
+                .map(|tone| (root + tone).rem_euclid(12) as usize)
+                .collect();
+            let matched: f64 = classes.iter().map(|class| weights[*class]).sum();
+            let missing = classes
+                .iter()
+                .filter(|class| weights[**class] == 0.0)
+                .count();
+            let score = 2.0 * matched - total - missing as f64 * (end - start);
+            let root_weight = weights[root as usize];
+            let better = match &best {
+                Some((_, best_score, best_root)) => {
+                    score > best_score + EPSILON
+                        || (score > best_score - EPSILON && root_weight > best_root + EPSILON)
+                }
+                None => true,
+            };
+            if better {
+                best = Some((symbol, score, root_weight));
+            }
+        }
+    }
+    let (symbol, score, _) = best.unwrap();
+    (symbol, score)
+}
+
+fn segment(notes: &[Note], key: &Key, start: f64, end: f64) -> Segment {
+    let (mut symbol, _) = best_chord(notes, start, end);
+    let indices = sounding(notes, start, end);
+    let bass = indices
+        .iter()
+        .map(|i| &notes[*i])
+        .filter(|note| note.onset < start + EPSILON)
+        .min_by(|a, b| a.pitch.0.partial_cmp(&b.pitch.0).unwrap_or(Ordering::Equal))
+        .map(|note| class(&note.pitch));
+    let tones = symbol.tones();
+    symbol.bass = bass.filter(|bass| {
+        *bass != symbol.root && tones.contains(&(bass - symbol.root).rem_euclid(12))
+    });
+    Segment {
+        onset: start,
+        duration: end - start,
+        notes: indices,
+        numeral: key.numeral(&symbol),
+        symbol,
+    }
+}
+
+fn is_chord_tone(symbol: &ChordSymbol, pitch: &Pitch) -> bool {
+    let tone = (class(pitch) - symbol.root).rem_euclid(12);
+    symbol.tones().contains(&tone)
+}
+
+fn cadences(segments: &[Segment], notes: &[Note], key: &Key) -> Vec<(usize, Cadence)> {
+    let tonic = class(&key.degree_pitch(0));
+    let degree = |symbol: &ChordSymbol| {
+        (0..7).find(|degree| class(&key.degree_pitch(*degree)) == symbol.root)
+    };
+    let dominant = |symbol: &ChordSymbol| {
+        degree(symbol) == Some(4)
+            && symbol.quality == Quality::Major
+            && symbol.seventh.is_none_or(|seventh| seventh == 10)
+    };
+    let leading = |symbol: &ChordSymbol| {
+        (symbol.root - tonic).rem_euclid(12) == 11 && symbol.quality == Quality::Diminished
+    };
+
+    let mut cadences = vec![];
+    for (i, segment) in segments.iter().enumerate() {
+        let symbol = &segment.symbol;
+        let previous = i.checked_sub(1).map(|p| &segments[p].symbol);
+        let phrase_end = segments
+            .get(i + 1)
+            .is_none_or(|next| next.onset > segment.onset + segment.duration + EPSILON);
+        let cadence = match previous {
+            Some(previous) if symbol.root == tonic && dominant(previous) => {
+                let top = segment
+                    .notes
+                    .iter()
+                    .map(|n| &notes[*n])
+                    .filter(|note| {
+                        note.onset + note.duration > segment.onset + segment.duration - EPSILON
+                    })
+                    .max_by(|a, b| a.pitch.0.partial_cmp(&b.pitch.0).unwrap_or(Ordering::Equal));
+                let perfect = previous.bass.is_none()
+                    && symbol.bass.is_none()
+                    && top.is_some_and(|note| class(&note.pitch) == tonic);
+                if perfect {
+                    Some(Cadence::PerfectAuthentic)
+                } else {
+                    Some(Cadence::ImperfectAuthentic)
+                }
+            }
+            Some(previous) if symbol.root == tonic && leading(previous) => {
+                Some(Cadence::ImperfectAuthentic)
+            }
+            Some(previous) if symbol.root == tonic && degree(previous) == Some(3) => {
+                Some(Cadence::Plagal)
+            }
+            Some(previous) if degree(symbol) == Some(5) && dominant(previous) => {
+                Some(Cadence::Deceptive)
+            }
+            _ if phrase_end && dominant(symbol) => Some(Cadence::Half),
+            _ => None,
+        };
+        cadences.extend(cadence.map(|cadence| (i, cadence)));
+    }
+    cadences
+}
+
+// Semitones from one pitch to another, rounded.
+fn motion(from: &Note, to: &Note) -> i32 {
+    (from.pitch.cents_to(&to.pitch) / 100.0).round() as i32
+}
+
+// The notes before and after `note` in its line.
+fn neighbours(notes: &[Note], index: usize) -> (Option<&Note>, Option<&Note>) {
+    let note = &notes[index];
+    let line = || {
+        notes
+            .iter()
+            .enumerate()
+            .filter(move |(i, other)| *i != index && other.instrument == note.instrument)
+            .map(|(_, other)| other)
+    };
+    // Orders by onset, latest or earliest first, then by closeness in pitch.
+    let nearest = |a: &&Note, b: &&Note, later: bool| {
+        let by_time = a.onset.partial_cmp(&b.onset).unwrap_or(Ordering::Equal);
+        let by_time = if later { by_time } else { by_time.reverse() };
+        by_time.then_with(|| {
+            let distance = |other: &Note| note.pitch.cents_to(&other.pitch).abs();
+            distance(b)
+                .partial_cmp(&distance(a))
+                .unwrap_or(Ordering::Equal)
+        })
+    };
+    let previous = line()
+        .filter(|other| other.onset < note.onset - EPSILON)
+        .max_by(|a, b| nearest(a, b, true));
+    let next = line()
+        .filter(|other| other.onset > note.onset + EPSILON)
+        .max_by(|a, b| nearest(a, b, false));
+    (previous, next)
+}
+
+fn non_chord_tones(segments: &[Segment], notes: &[Note]) -> Vec<(usize, NonChordTone)> {
+    let mut labels = vec![];
+    for (index, note) in notes.iter().enumerate() {
+        let spans: Vec<usize> = (0..segments.len())
+            .filter(|s| segments[*s].notes.contains(&index))
+            .collect();
+        let outside = match spans
+            .iter()
+            .find(|s| !is_chord_tone(&segments[**s].symbol, &note.pitch))
+        {
+            Some(outside) => *outside,
+            None => continue,
+        };
+        let held = note.onset < segments[outside].onset - EPSILON;
+        let (previous, next) = neighbours(notes, index);
+        let into = previous.map(|previous| motion(previous, note));
+        let out = next.map(|next| motion(note, next));
+        let step = |interval: Option<i32>| interval.is_some_and(|i| i != 0 && i.abs() <= 2);
+        let leap = |interval: Option<i32>| interval.is_some_and(|i| i.abs() > 2);
+        let kind = if spans.len() >= 3 {
+            NonChordTone::Pedal
+        } else if (held || into == Some(0)) && step(out) {
+            NonChordTone::Suspension
+        } else if out == Some(0) {
+            NonChordTone::Anticipation
+        } else if step(into) && step(out) {
+            if into.unwrap().signum() == out.unwrap().signum() {
+                NonChordTone::Passing
+            } else {
+                NonChordTone::Neighbour
+            }
+        } else if leap(into) && step(out) {
+            NonChordTone::Appoggiatura
+        } else if step(into) && leap(out) {
+            NonChordTone::Escape
+        } else {
+            NonChordTone::Other
+        };
+        labels.push((index, kind));
+    }
+    labels
+}
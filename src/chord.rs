@@ -0,0 +1,786 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+
+use super::tuning::Tuning;
+use super::{parse_pitch_class, Key, Pitch};
+
+const LEAD_SHEET_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+
+#[derive(Clone, Debug)]
+pub struct Chord(pub Vec<Pitch>);
+impl Chord {
+    /// Builds the chord named by a lead-sheet symbol such as "Cmaj7" or
+    /// "G7(#9)/B" from keys of `tuning`, with the root in `octave` (where
+    /// octave 4 starts at middle C) and any slash bass below it.
+    pub fn from_symbol(
+        symbol: &str,
+        tuning: &Tuning,
+        octave: i32,
+    ) -> Result<Chord, ParseChordError> {
+        Ok(symbol.parse::<ChordSymbol>()?.to_chord(tuning, octave))
+    }
+
+    /// Names this chord, reading each pitch as the nearest key of `tuning`.
+    pub fn symbol(&self, tuning: &Tuning) -> Option<ChordSymbol> {
+        ChordSymbol::identify(self, tuning)
+    }
+
+    /// Moves the first pitch an octave towards the second. Chords of fewer
+    /// than two pitches are returned unchanged.
+    pub fn invert(&self) -> Self {
+        let mut chord = self.clone();
+        if chord.0.len() < 2 {
+            return chord;
+        }
+        if chord.0[0].0 < chord.0[1].0 {
+            chord.0[0] = chord.0[0].apply_interval(2.0);
+        } else {
+            chord.0[0] = chord.0[0].apply_interval(0.5);
+        }
+        chord
+    }
+
+    /// The chord's pitches from lowest to highest.
+    pub fn sorted(&self) -> Self {
+        let mut chord = self.clone();
+        chord.0.sort();
+        chord
+    }
+
+    /// Close position: the lowest pitch stays put and every other pitch is
+    /// moved by octaves to within the octave above it.
+    pub fn close(&self) -> Self {
+        let mut chord = self.sorted();
+        if let Some(&bass) = chord.0.first() {
+            for pitch in &mut chord.0[1..] {
+                *pitch = above(*pitch, bass);
+            }
+        }
+        chord.sorted()
+    }
+
+    /// The `n`th inversion of the close position chord, so the first
+    /// inversion of C E G is E G C. Inversions past the last chord tone
+    /// carry on up the octave.
+    pub fn inversion(&self, n: usize) -> Self {
+        let mut chord = self.close();
+        for _ in 0..n {
+            if chord.0.is_empty() {
+                break;
+            }
+            let lowest = chord.0.remove(0);
+            chord.0.push(lowest.apply_interval(2.0));
+        }
+        chord
+    }
+
+    /// Open position: every second pitch of the close position chord, counting
+    /// up from the bass, is raised an octave.
+    pub fn open(&self) -> Self {
+        let mut chord = self.close();
+        for pitch in chord.0.iter_mut().skip(1).step_by(2) {
+            *pitch = pitch.apply_interval(2.0);
+        }
+        chord.sorted()
+    }
+
+    /// Drops the `voice`th pitch from the top of the close position chord by
+    /// an octave. Chords with fewer pitches are only closed.
+    pub fn drop(&self, voice: usize) -> Self {
+        let mut chord = self.close();
+        let len = chord.0.len();
+        if voice > 0 && voice <= len {
+            chord.0[len - voice] = chord.0[len - voice].apply_interval(0.5);
+        }
+        chord.sorted()
+    }
+
+    pub fn drop2(&self) -> Self {
+        self.drop(2)
+    }
+
+    pub fn drop3(&self) -> Self {
+        self.drop(3)
+    }
+
+    /// Moves each pitch by octaves into the range from `low` up to `high`.
+    /// Ranges narrower than an octave get the transposition nearest to them.
+    pub fn spread(&self, low: &Pitch, high: &Pitch) -> Self {
+        let middle = Pitch((low.0 * high.0).sqrt());
+        let chord = Chord(
+            self.0
+                .iter()
+                .map(|pitch| {
+                    let pitch = above(*pitch, *low);
+                    if pitch.0 < high.0 {
+                        pitch
+                    } else {
+                        nearest_octave(pitch, &middle)
+                    }
+                })
+                .collect(),
+        );
+        chord.sorted()
+    }
+
+    /// Chooses an octave for each of this chord's pitches so that moving from
+    /// `previous` takes as little total motion as possible. When both chords
+    /// have the same number of pitches (up to eight) every pairing of voices
+    /// is tried, otherwise each pitch settles near the closest pitch of
+    /// `previous`.
+    pub fn voice_lead_from(&self, previous: &Chord) -> Self {
+        let previous = previous.sorted();
+        if previous.0.is_empty() {
+            return self.clone();
+        }
+        if self.0.len() == previous.0.len() && self.0.len() <= 8 {
+            let mut best = (f64::INFINITY, vec![]);
+            let mut order: Vec<usize> = (0..self.0.len()).collect();
+            permutations(&mut order, 0, &mut |order| {
+                let voiced: Vec<Pitch> = order
+                    .iter()
+                    .zip(&previous.0)
+                    .map(|(i, from)| nearest_octave(self.0[*i], from))
+                    .collect();
+                let motion: f64 = voiced
+                    .iter()
+                    .zip(&previous.0)
+                    .map(|(to, from)| from.cents_to(to).abs())
+                    .sum();
+                if motion < best.0 {
+                    best = (motion, voiced);
+                }
+            });
+            return Chord(best.1).sorted();
+        }
+        let chord = Chord(
+            self.0
+                .iter()
+                .map(|pitch| {
+                    let (_, voiced) = previous
+                        .0
+                        .iter()
+                        .map(|from| {
+                            let to = nearest_octave(*pitch, from);
+                            (from.cents_to(&to).abs(), to)
+                        })
+                        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+                        .unwrap();
+                    voiced
+                })
+                .collect(),
+        );
+        chord.sorted()
+    }
+
+    /// Moves every pitch but the first up or down an octave at random.
+    pub fn randomize_voicing<R: Rng>(&self, rng: &mut R) -> Self {
+        let mut chord = self.clone();
+        for pitch in chord.0.iter_mut().skip(1) {
+            if rng.gen::<f32>() > 0.5 {
+                *pitch = pitch.apply_interval(2.0);
+            } else {
+                *pitch = pitch.apply_interval(0.5);
+            }
+        }
+        chord
+    }
+}
+
+/// The triad a chord is built on. Suspended chords replace the third with a
+/// second or fourth and power chords have no third at all.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Quality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Suspended2,
+    Suspended4,
+    Power,
+}
+
+impl Quality {
+    pub(crate) fn third(self) -> Option<i32> {
+        match self {
+            Quality::Major | Quality::Augmented => Some(4),
+            Quality::Minor | Quality::Diminished => Some(3),
+            Quality::Suspended2 => Some(2),
+            Quality::Suspended4 => Some(5),
+            Quality::Power => None,
+        }
+    }
+
+    fn fifth(self) -> i32 {
+        match self {
+            Quality::Diminished => 6,
+            Quality::Augmented => 8,
+            _ => 7,
+        }
+    }
+}
+
+/// A chord as written on a lead sheet. Pitch classes count semitones up from
+/// C and chord tones count semitones up from the root, so a ninth is 14 and
+/// a sharp eleventh 18.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChordSymbol {
+    pub root: i32,
+    pub quality: Quality,
+    /// `None` when the fifth is omitted.
+    pub fifth: Option<i32>,
+    pub seventh: Option<i32>,
+    /// Tones above the seventh and added tones, in ascending order.
+    pub extensions: Vec<i32>,
+    pub bass: Option<i32>,
+}
+
+impl ChordSymbol {
+    pub fn new(root: i32, quality: Quality) -> ChordSymbol {
+        ChordSymbol {
+            root: root.rem_euclid(12),
+            quality,
+            fifth: Some(quality.fifth()),
+            seventh: None,
+            extensions: vec![],
+            bass: None,
+        }
+    }
+
+    /// Chord tones in semitones above the root, lowest first, not counting
+    /// the bass.
+    pub fn tones(&self) -> Vec<i32> {
+        let mut tones = vec![0];
+        tones.extend(self.quality.third());
+        tones.extend(self.fifth);
+        tones.extend(self.seventh);
+        tones.extend(self.extensions.iter().cloned());
+        tones.sort();
+        tones.dedup();
+        tones
+    }
+
+    pub fn to_chord(&self, tuning: &Tuning, octave: i32) -> Chord {
+        let root_key = (octave + 1) * 12 + self.root;
+        let mut keys: Vec<i32> = self.tones().iter().map(|tone| root_key + tone).collect();
+        if let Some(bass) = self.bass {
+            keys.insert(0, root_key + (bass - self.root).rem_euclid(12) - 12);
+        }
+        Chord(
+            keys.into_iter()
+                .filter_map(|key| tuning.key_pitch(key))
+                .collect(),
+        )
+    }
+
+    /// Names `chord`, taking its lowest pitch as the bass and trying each of
+    /// its pitch classes as the root. Readings are preferred with few
+    /// extensions, altered ones counting against a reading more than natural
+    /// ones, then with the root in the bass. Returns `None` for empty chords
+    /// and sets of tones that don't fit a tertian chord.
+    pub fn identify(chord: &Chord, tuning: &Tuning) -> Option<ChordSymbol> {
+        let mut classes: Vec<i32> = chord
+            .sorted()
+            .0
+            .iter()
+            .filter_map(|pitch| nearest_key(pitch, tuning))
+            .collect();
+        let bass = *classes.first()?;
+        classes.sort();
+        classes.dedup();
+        let mut best: Option<(usize, ChordSymbol)> = None;
+        for root in &classes {
+            let tones: Vec<i32> = classes.iter().map(|c| (c - root).rem_euclid(12)).collect();
+            if let Some(mut symbol) = fit(*root, &tones) {
+                let mut score: usize = symbol
+                    .extensions
+                    .iter()
+                    .map(|extension| match extension {
+                        9 | 14 | 17 | 21 => 1,
+                        _ => 3,
+                    })
+                    .sum();
+                match symbol.fifth {
+                    None => score += 2,
+                    Some(fifth) if fifth != symbol.quality.fifth() => score += 2,
+                    _ => (),
+                }
+                if let Quality::Suspended2 | Quality::Suspended4 = symbol.quality {
+                    score += 1;
+                }
+                if symbol.quality == Quality::Diminished && symbol.seventh == Some(11) {
+                    score += 3;
+                }
+                if *root != bass {
+                    symbol.bass = Some(bass);
+                    score += 2;
+                }
+                if best.as_ref().is_none_or(|(best, _)| score < *best) {
+                    best = Some((score, symbol));
+                }
+            }
+        }
+        best.map(|(_, symbol)| symbol)
+    }
+}
+
+// The octave transposition of `pitch` at or above `floor` and below twice it.
+fn above(pitch: Pitch, floor: Pitch) -> Pitch {
+    let octaves = (f64::from(floor.0) / f64::from(pitch.0)).log2().ceil();
+    let pitch = pitch.apply_interval(2.0f64.powf(octaves));
+    // Guard against rounding leaving the pitch a hair below the floor
+    if pitch.0 < floor.0 {
+        pitch.apply_interval(2.0)
+    } else {
+        pitch
+    }
+}
+
+fn nearest_octave(pitch: Pitch, target: &Pitch) -> Pitch {
+    let octaves = (f64::from(target.0) / f64::from(pitch.0)).log2().round();
+    pitch.apply_interval(2.0f64.powf(octaves))
+}
+
+// Calls `visit` with every ordering of `order[start..]`.
+fn permutations<F: FnMut(&[usize])>(order: &mut [usize], start: usize, visit: &mut F) {
+    if start == order.len() {
+        visit(order);
+        return;
+    }
+    for i in start..order.len() {
+        order.swap(start, i);
+        permutations(order, start + 1, visit);
+        order.swap(start, i);
+    }
+}
+
+// Reads semitones above `root` as a chord, or `None` if some tone has no
+// place in it.
+fn fit(root: i32, tones: &[i32]) -> Option<ChordSymbol> {
+    let has = |tone| tones.contains(&tone);
+    let quality = if has(4) {
+        if !has(7) && has(8) {
+            Quality::Augmented
+        } else {
+            Quality::Major
+        }
+    } else if has(3) {
+        if !has(7) && has(6) {
+            Quality::Diminished
+        } else {
+            Quality::Minor
+        }
+    } else if has(5) {
+        Quality::Suspended4
+    } else if has(2) {
+        Quality::Suspended2
+    } else {
+        Quality::Power
+    };
+    let mut symbol = ChordSymbol::new(root, quality);
+    symbol.fifth = [quality.fifth(), 7, 6]
+        .iter()
+        .cloned()
+        .find(|fifth| has(*fifth));
+    symbol.seventh = if has(11) {
+        Some(11)
+    } else if has(10) {
+        Some(10)
+    } else if quality == Quality::Diminished && has(9) {
+        Some(9)
+    } else {
+        None
+    };
+    let mut used = vec![0];
+    used.extend(quality.third());
+    used.extend(symbol.fifth);
+    used.extend(symbol.seventh);
+    for &tone in tones.iter().filter(|tone| !used.contains(tone)) {
+        let extension = match tone {
+            9 if symbol.seventh.is_none() => 9,
+            1..=9 => tone + 12,
+            _ => return None,
+        };
+        symbol.extensions.push(extension);
+    }
+    if quality == Quality::Power
+        && (symbol.fifth != Some(7) || symbol.seventh.is_some() || !symbol.extensions.is_empty())
+    {
+        return None;
+    }
+    symbol.extensions.sort();
+    Some(symbol)
+}
+
+fn nearest_key(pitch: &Pitch, tuning: &Tuning) -> Option<i32> {
+    let distance = |key: &i32| {
+        tuning
+            .key_pitch(*key)
+            .map_or(f64::INFINITY, |tuned| tuned.cents_to(pitch).abs())
+    };
+    (0..128)
+        .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+        .filter(|key| distance(key).is_finite())
+        .map(|key| key.rem_euclid(12))
+}
+
+// Semitones above the root of the natural chord tone for each degree.
+fn degree_semitones(degree: i32) -> Option<i32> {
+    match degree {
+        2 => Some(2),
+        4 => Some(5),
+        5 => Some(7),
+        6 => Some(9),
+        7 => Some(10),
+        9 => Some(14),
+        11 => Some(17),
+        13 => Some(21),
+        _ => None,
+    }
+}
+
+impl FromStr for ChordSymbol {
+    type Err = ParseChordError;
+
+    /// Parses symbols such as "C", "Am7", "Bbmaj9", "F#m7b5", "Ddim7",
+    /// "G7(#9)/B", "Esus4", "A6/9", "Cadd9" and "C5".
+    fn from_str(s: &str) -> Result<ChordSymbol, ParseChordError> {
+        let invalid = || ParseChordError::InvalidSymbol(s.to_string());
+        let (root, rest) =
+            parse_pitch_class(s.trim()).map_err(|_| ParseChordError::InvalidRoot(s.to_string()))?;
+        let (mut rest, bass) = match rest.rfind('/') {
+            Some(slash) if !rest[slash..].starts_with("/9") => {
+                let (bass, after) = parse_pitch_class(&rest[slash + 1..])
+                    .map_err(|_| ParseChordError::InvalidRoot(s.to_string()))?;
+                if !after.is_empty() {
+                    return Err(invalid());
+                }
+                (&rest[..slash], Some(bass.rem_euclid(12)))
+            }
+            _ => (rest, None),
+        };
+
+        let mut eat = |prefixes: &[&str]| {
+            for prefix in prefixes {
+                if rest.starts_with(prefix) {
+                    rest = &rest[prefix.len()..];
+                    return true;
+                }
+            }
+            false
+        };
+        let mut quality = Quality::Major;
+        let mut seventh = 10;
+        let mut half_diminished = false;
+        if eat(&["maj", "Maj", "ma", "M", "Δ"]) {
+            seventh = 11;
+        } else if eat(&["min", "mi", "m", "-"]) {
+            quality = Quality::Minor;
+            if eat(&["(maj", "maj", "Maj", "M", "Δ"]) {
+                seventh = 11;
+            }
+        } else if eat(&["dim", "°", "o"]) {
+            quality = Quality::Diminished;
+            seventh = 9;
+        } else if eat(&["ø"]) {
+            quality = Quality::Diminished;
+            half_diminished = true;
+        } else if eat(&["aug", "+"]) {
+            quality = Quality::Augmented;
+        }
+        let mut symbol = ChordSymbol::new(root, quality);
+        symbol.bass = bass;
+        if half_diminished {
+            symbol.seventh = Some(10);
+        }
+
+        let mut modifiers = rest
+            .split(&['(', ')', ',', ' '][..])
+            .filter(|modifier| !modifier.is_empty());
+        let mut modifier = modifiers.next();
+        if let Some(number) = modifier.and_then(leading_number) {
+            let text = modifier.unwrap();
+            let after = &text[number.to_string().len()..];
+            match number {
+                5 if quality == Quality::Major && seventh == 10 => {
+                    symbol.quality = Quality::Power;
+                }
+                6 => {
+                    symbol.extensions.push(9);
+                    if after.starts_with("/9") {
+                        symbol.extensions.push(14);
+                    }
+                }
+                69 => symbol.extensions.extend(&[9, 14]),
+                7 | 9 | 11 | 13 => {
+                    symbol.seventh = Some(seventh);
+                    if number >= 9 {
+                        symbol.extensions.push(14);
+                    }
+                    if number == 11 {
+                        symbol.extensions.push(17);
+                    }
+                    if number == 13 {
+                        symbol.extensions.push(21);
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+            let after = after.trim_start_matches("/9");
+            modifier = if after.is_empty() {
+                modifiers.next()
+            } else {
+                Some(after)
+            };
+        }
+
+        // Remaining modifiers may run together, as in "7b5b9sus4".
+        let mut pending = String::new();
+        while let Some(text) = modifier {
+            pending.push_str(text);
+            modifier = modifiers.next();
+        }
+        let mut rest = pending.as_str();
+        while !rest.is_empty() {
+            if let Some(after) = strip(rest, &["sus2"]) {
+                symbol.quality = Quality::Suspended2;
+                rest = after;
+            } else if let Some(after) = strip(rest, &["sus4", "sus"]) {
+                symbol.quality = Quality::Suspended4;
+                rest = after;
+            } else if let Some(after) = strip(rest, &["maj7", "Maj7", "M7"]) {
+                symbol.seventh = Some(11);
+                rest = after;
+            } else if let Some(after) = strip(rest, &["no5", "omit5"]) {
+                symbol.fifth = None;
+                rest = after;
+            } else if let Some(after) = strip(rest, &["add"]) {
+                let number = leading_number(after).ok_or_else(invalid)?;
+                let tone = degree_semitones(number).ok_or_else(invalid)?;
+                // An added second or fourth sits above the third
+                symbol
+                    .extensions
+                    .push(if tone < 7 { tone + 12 } else { tone });
+                rest = &after[number.to_string().len()..];
+            } else if let Some(after) = strip(rest, &["b", "#", "+", "-"]) {
+                let sharp = rest.starts_with('#') || rest.starts_with('+');
+                let number = leading_number(after).ok_or_else(invalid)?;
+                let natural = degree_semitones(number)
+                    .filter(|_| [5, 9, 11, 13].contains(&number))
+                    .ok_or_else(invalid)?;
+                let tone = natural + if sharp { 1 } else { -1 };
+                if number == 5 {
+                    symbol.fifth = Some(tone);
+                } else {
+                    symbol.extensions.retain(|extension| *extension != natural);
+                    symbol.extensions.push(tone);
+                }
+                rest = &after[number.to_string().len()..];
+            } else {
+                return Err(invalid());
+            }
+        }
+        match (symbol.quality, symbol.fifth) {
+            (Quality::Minor, Some(6)) => symbol.quality = Quality::Diminished,
+            (Quality::Major, Some(8)) => symbol.quality = Quality::Augmented,
+            _ => (),
+        }
+        symbol.extensions.sort();
+        symbol.extensions.dedup();
+        Ok(symbol)
+    }
+}
+
+fn strip<'a>(text: &'a str, prefixes: &[&str]) -> Option<&'a str> {
+    prefixes
+        .iter()
+        .find(|prefix| text.starts_with(*prefix))
+        .map(|prefix| &text[prefix.len()..])
+}
+
+fn leading_number(text: &str) -> Option<i32> {
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    text[..digits].parse().ok()
+}
+
+impl ChordSymbol {
+    /// The symbol with its root and bass spelled as in `key`, so "A#maj7"
+    /// reads "Bbmaj7" in F major and stays "A#maj7" in B major.
+    pub fn spell(&self, key: &Key) -> String {
+        let name = |class: i32| key.spell_class(&Pitch::from_midi(f64::from(60 + class)));
+        let mut text = String::new();
+        self.write(&mut text, name).unwrap();
+        text
+    }
+
+    fn write<W: fmt::Write, N: Fn(i32) -> String>(&self, f: &mut W, name: N) -> fmt::Result {
+        write!(f, "{}", name(self.root.rem_euclid(12)))?;
+        let mut extensions = self.extensions.clone();
+        let mut take = |tone: i32| {
+            let found = extensions.contains(&tone);
+            extensions.retain(|extension| *extension != tone);
+            found
+        };
+
+        let number = match self.seventh {
+            Some(_) if take(21) => {
+                take(14);
+                Some(13)
+            }
+            Some(_) if take(17) => {
+                take(14);
+                Some(11)
+            }
+            Some(_) if take(14) => Some(9),
+            Some(9) if self.quality == Quality::Diminished => Some(7),
+            Some(_) => Some(7),
+            None if take(9) => Some(if take(14) { 69 } else { 6 }),
+            None => None,
+        };
+        let half_diminished = self.quality == Quality::Diminished && self.seventh == Some(10);
+        let quality = match self.quality {
+            Quality::Minor => "m",
+            Quality::Diminished if half_diminished => "m",
+            Quality::Diminished => "dim",
+            Quality::Augmented => "+",
+            Quality::Power => "5",
+            _ => "",
+        };
+        write!(f, "{}", quality)?;
+        match (self.seventh, self.quality) {
+            (Some(11), Quality::Minor) => write!(f, "Maj")?,
+            (Some(11), _) => write!(f, "maj")?,
+            _ => (),
+        }
+        match number {
+            Some(69) => write!(f, "6/9")?,
+            Some(number) => write!(f, "{}", number)?,
+            None => (),
+        }
+        match self.quality {
+            Quality::Suspended2 => write!(f, "sus2")?,
+            Quality::Suspended4 => write!(f, "sus4")?,
+            _ => (),
+        }
+
+        let mut alterations = vec![];
+        match self.fifth {
+            Some(fifth) if half_diminished || fifth == 6 && self.quality != Quality::Diminished => {
+                alterations.push("b5".to_string())
+            }
+            Some(8) if self.quality != Quality::Augmented => alterations.push("#5".to_string()),
+            None if self.quality != Quality::Power => alterations.push("no5".to_string()),
+            _ => (),
+        }
+        for extension in extensions {
+            alterations.push(match extension {
+                13 => "b9".to_string(),
+                15 => "#9".to_string(),
+                18 => "#11".to_string(),
+                20 => "b13".to_string(),
+                14 => "add9".to_string(),
+                17 => "add11".to_string(),
+                21 => "add13".to_string(),
+                9 => "add6".to_string(),
+                other => format!("add{}", other),
+            });
+        }
+        match alterations.len() {
+            0 => (),
+            // A bare flat straight after the root would read as part of it
+            1 if alterations[0] == "b5" && number.is_some()
+                || alterations[0].starts_with("add") =>
+            {
+                write!(f, "{}", alterations[0])?
+            }
+            _ => write!(f, "({})", alterations.join(","))?,
+        }
+        if let Some(bass) = self.bass {
+            write!(f, "/{}", name(bass.rem_euclid(12)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Roots and basses are spelled as is usual on lead sheets, with flats but
+/// for F#. `ChordSymbol::spell` spells them for a key.
+impl fmt::Display for ChordSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, |class| LEAD_SHEET_NAMES[class as usize].to_string())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseChordError {
+    InvalidRoot(String),
+    InvalidSymbol(String),
+}
+
+impl fmt::Display for ParseChordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseChordError::InvalidRoot(s) => write!(f, "invalid root or bass in '{}'", s),
+            ParseChordError::InvalidSymbol(s) => write!(f, "invalid chord symbol '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseChordError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYMBOLS: [&str; 17] = [
+        "C", "Cm", "Bbmaj9", "Cm/Eb", "F#m7b5", "Ebdim7", "G7(#9)/B", "Ab+", "Dsus4", "C6/9",
+        "AmMaj7", "Db13", "E5", "Gadd9", "Bm11", "C7sus4", "A7b5",
+    ];
+
+    #[test]
+    fn symbols_display_as_parsed() {
+        for text in SYMBOLS.iter() {
+            let symbol: ChordSymbol = text.parse().unwrap();
+            assert_eq!(symbol.to_string(), *text);
+        }
+        let sharp: ChordSymbol = "A#maj9".parse().unwrap();
+        assert_eq!(sharp.to_string(), "Bbmaj9");
+    }
+
+    #[test]
+    fn chords_are_identified_as_built() {
+        let tuning = Tuning::equal(12);
+        for text in SYMBOLS.iter() {
+            let symbol: ChordSymbol = text.parse().unwrap();
+            let chord = symbol.to_chord(&tuning, 4);
+            assert_eq!(
+                ChordSymbol::identify(&chord, &tuning),
+                Some(symbol),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn the_bass_is_the_lowest_pitch() {
+        let tuning = Tuning::equal(12);
+        let pitch = |name: &str| name.parse::<Pitch>().unwrap();
+        let chord = Chord(vec![pitch("G4"), pitch("C3"), pitch("E4")]);
+        assert_eq!(chord.symbol(&tuning).unwrap().to_string(), "C");
+        let chord = Chord(vec![pitch("C4"), pitch("E3"), pitch("G4")]);
+        assert_eq!(chord.symbol(&tuning).unwrap().to_string(), "C/E");
+    }
+
+    #[test]
+    fn spelling_follows_the_key() {
+        let symbol: ChordSymbol = "A#maj7/D".parse().unwrap();
+        assert_eq!(symbol.spell(&"F major".parse().unwrap()), "Bbmaj7/D");
+        assert_eq!(symbol.spell(&"B major".parse().unwrap()), "A#maj7/D");
+        let symbol: ChordSymbol = "Cm/D#".parse().unwrap();
+        assert_eq!(symbol.spell(&"C minor".parse().unwrap()), "Cm/Eb");
+    }
+}
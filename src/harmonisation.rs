@@ -0,0 +1,259 @@
+use super::synth::Note;
+use super::tuning::Tuning;
+use super::voice_leading::VoiceLeader;
+use super::{Chord, Key};
+
+// How many voicings of each chord are tried before backtracking.
+const VOICINGS: usize = 3;
+// Chords abandoned for a later note having no way on before giving up on a
+// melody.
+const BUDGET: usize = 20_000;
+// How far in cents a melody note may lie from a chord tone it belongs to.
+const MELODY_TOLERANCE: f64 = 50.0;
+// The cost, in cents of voice motion, of not starting on the tonic or not
+// approaching the final tonic from the dominant.
+const RULE_PENALTY: f64 = 2400.0;
+
+/// A four part setting of a melody.
+#[derive(Clone, Debug)]
+pub struct Harmonisation {
+    /// The voiced chord under each melody note, bass first.
+    pub chords: Vec<Chord>,
+    pub numerals: Vec<String>,
+    /// The bass, tenor, alto and soprano notes, the soprano being the melody.
+    pub voices: Vec<Vec<Note>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Function {
+    Tonic,
+    Predominant,
+    Dominant,
+}
+
+struct Candidate {
+    chord: Chord,
+    degree: i32,
+    numeral: String,
+}
+
+/// Harmonises `melody`, a single line, in four parts with a root position
+/// diatonic triad, or a dominant seventh, under each note. Chords follow the
+/// usual tonic, predominant, dominant order, the first is the tonic where
+/// the melody allows, the last must be, and the one before it is a dominant
+/// where possible. Voices are led by `VoiceLeader::satb` with the soprano
+/// fixed to the chord tone nearest the melody, backtracking when a chord
+/// leaves no way on. Returns `None` for keys that aren't based on seven
+/// note scales and melodies that can't be set under these rules.
+///
+/// The notes of each voice go to the matching entry of `instruments`, bass
+/// first, and take the rhythm of the melody.
+pub fn harmonise(melody: &[Note], key: &Key, instruments: [usize; 4]) -> Option<Harmonisation> {
+    if key.scale.len() != 7 {
+        return None;
+    }
+    let mut search = Search {
+        melody,
+        candidates: candidates(key),
+        leader: VoiceLeader::satb(),
+        chosen: vec![],
+    };
+    if !search.extend() {
+        return None;
+    }
+
+    let mut voices = vec![vec![]; 4];
+    for (note, (_, chord)) in melody.iter().zip(&search.chosen) {
+        for (voice, pitch) in chord.0.iter().enumerate().take(3) {
+            voices[voice].push(Note {
+                instrument: instruments[voice],
+                pitch: *pitch,
+                onset: note.onset,
+                duration: note.duration,
+                amplitude: note.amplitude,
+                parameters: vec![],
+            });
+        }
+        voices[3].push(Note {
+            instrument: instruments[3],
+            ..note.clone()
+        });
+    }
+    let numerals = search
+        .chosen
+        .iter()
+        .map(|(candidate, _)| search.candidates[*candidate].numeral.clone())
+        .collect();
+    Some(Harmonisation {
+        chords: search.chosen.into_iter().map(|(_, chord)| chord).collect(),
+        numerals,
+        voices,
+    })
+}
+
+// The triad on each degree of the scale but the seventh, whose root position
+// is avoided, and the major dominant triad and seventh, all in root
+// position.
+fn candidates(key: &Key) -> Vec<Candidate> {
+    let tuning = Tuning::equal(12);
+    let mut chords: Vec<(Chord, i32)> = (0..6)
+        .map(|degree| {
+            let chord = Chord(
+                [0, 2, 4]
+                    .iter()
+                    .map(|step| key.degree_pitch(degree + step))
+                    .collect(),
+            );
+            (chord, degree)
+        })
+        .collect();
+    for numeral in &["V", "V7"] {
+        let chord = key.chord(numeral).unwrap();
+        if !chords.iter().any(|(other, _)| other.0 == chord.0) {
+            chords.push((chord, 4));
+        }
+    }
+    chords
+        .into_iter()
+        .filter_map(|(chord, degree)| {
+            let numeral = key.numeral(&chord.symbol(&tuning)?)?;
+            // Augmented triads, as on the mediant of a harmonic minor scale,
+            // don't take part in these progressions
+            if numeral.contains('+') {
+                return None;
+            }
+            Some(Candidate {
+                chord,
+                degree,
+                numeral,
+            })
+        })
+        .collect()
+}
+
+fn function(degree: i32) -> Function {
+    match degree {
+        1 | 3 => Function::Predominant,
+        4 => Function::Dominant,
+        _ => Function::Tonic,
+    }
+}
+
+// The penalty for moving from one candidate to another, or `None` if the
+// progression isn't allowed.
+fn progression(from: &Candidate, to: &Candidate) -> Option<f64> {
+    let penalty = match (function(from.degree), from.degree, to.degree) {
+        _ if from.chord.0 == to.chord.0 => 300.0,
+        (Function::Dominant, _, 0) | (Function::Dominant, _, 5) => 0.0,
+        // V may become V7 but a seventh doesn't go back
+        (Function::Dominant, _, 4) if from.chord.0.len() < to.chord.0.len() => 0.0,
+        (Function::Dominant, _, _) => return None,
+        (Function::Predominant, 1, 3) | (Function::Predominant, 1, 0) => return None,
+        (Function::Predominant, 3, 0) => 100.0,
+        (Function::Predominant, _, next) if function(next) == Function::Tonic => return None,
+        _ => 0.0,
+    };
+    Some(penalty + weight(to))
+}
+
+// How much less often a chord is used than the primary triads. This counts
+// against the mediant and, in minor keys, the dominant minor triad.
+fn weight(candidate: &Candidate) -> f64 {
+    if candidate.degree == 2 || candidate.numeral == "v" {
+        400.0
+    } else {
+        0.0
+    }
+}
+
+struct Search<'a> {
+    melody: &'a [Note],
+    candidates: Vec<Candidate>,
+    leader: VoiceLeader,
+    // The index of each chosen candidate and its voicing.
+    chosen: Vec<(usize, Chord)>,
+}
+
+impl<'a> Search<'a> {
+    // Chooses a chord for each note in turn, trying the cheapest first and
+    // backing up to the previous note when none is left.
+    fn extend(&mut self) -> bool {
+        // The options still to try for each note so far, cheapest last
+        let mut untried: Vec<Vec<(usize, Chord)>> = vec![];
+        let mut backtracks = 0;
+        while self.chosen.len() < self.melody.len() {
+            if untried.len() == self.chosen.len() {
+                let mut options = self.options();
+                options.reverse();
+                untried.push(options);
+            }
+            match untried.last_mut().unwrap().pop() {
+                Some(option) => self.chosen.push(option),
+                None => {
+                    untried.pop();
+                    if self.chosen.pop().is_none() {
+                        return false;
+                    }
+                    backtracks += 1;
+                    if backtracks > BUDGET {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // Each candidate and voicing that can follow the chords chosen so far
+    // under the next note, cheapest first.
+    fn options(&self) -> Vec<(usize, Chord)> {
+        let index = self.chosen.len();
+        let note = &self.melody[index];
+        let last = index + 1 == self.melody.len();
+        let penultimate = index + 2 == self.melody.len();
+        let mut leader = self.leader.clone();
+
+        let mut options = vec![];
+        for (i, candidate) in self.candidates.iter().enumerate() {
+            let tone = match candidate
+                .chord
+                .0
+                .iter()
+                .find(|pitch| pitch.same_class(&note.pitch, MELODY_TOLERANCE))
+            {
+                Some(tone) if !last || candidate.degree == 0 => tone,
+                _ => continue,
+            };
+            // The soprano takes the octave of the chord tone nearest the
+            // melody, which may be tuned a little differently
+            let octaves = (tone.cents_to(&note.pitch) / 1200.0).round();
+            let soprano = tone.apply_interval(2.0f64.powf(octaves));
+            leader.ranges[3] = (soprano.offset_cents(-1.0), soprano.offset_cents(1.0));
+            let mut penalty = match self.chosen.last() {
+                Some((previous, _)) => match progression(&self.candidates[*previous], candidate) {
+                    Some(penalty) => penalty,
+                    None => continue,
+                },
+                None if candidate.degree == 0 => 0.0,
+                None => RULE_PENALTY + weight(candidate),
+            };
+            if penultimate && function(candidate.degree) != Function::Dominant {
+                penalty += RULE_PENALTY;
+            }
+            let leadings = match self.chosen.last() {
+                Some((_, voicing)) => leader.leads(voicing, &candidate.chord, VOICINGS),
+                None => leader.voicings(&candidate.chord, VOICINGS),
+            };
+            options.extend(
+                leadings
+                    .into_iter()
+                    .map(|leading| (leading.distance + penalty, i, leading.target())),
+            );
+        }
+        options.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        options
+            .into_iter()
+            .map(|(_, i, voicing)| (i, voicing))
+            .collect()
+    }
+}
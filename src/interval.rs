@@ -0,0 +1,309 @@
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+
+use super::tuning::ratio_to_cents;
+
+// Semitones above the tonic of each diatonic step in a major scale.
+const DIATONIC_SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+// The nearest 12-TET interval name for each number of semitones in an octave.
+const SEMITONE_NAMES: [(char, i32); 12] = [
+    ('P', 1),
+    ('m', 2),
+    ('M', 2),
+    ('m', 3),
+    ('M', 3),
+    ('P', 4),
+    ('A', 4),
+    ('P', 5),
+    ('m', 6),
+    ('M', 6),
+    ('m', 7),
+    ('M', 7),
+];
+
+/// The distance between two pitches, either as an exact frequency ratio
+/// (kept in lowest terms) or, for intervals that have no rational form such
+/// as tempered ones, in cents.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interval {
+    Ratio { numerator: u64, denominator: u64 },
+    Cents(f64),
+}
+
+impl Interval {
+    pub const UNISON: Interval = Interval::Ratio {
+        numerator: 1,
+        denominator: 1,
+    };
+    pub const OCTAVE: Interval = Interval::Ratio {
+        numerator: 2,
+        denominator: 1,
+    };
+
+    pub fn new(numerator: u64, denominator: u64) -> Interval {
+        assert!(
+            numerator != 0 && denominator != 0,
+            "an interval ratio must be positive"
+        );
+        let divisor = gcd(numerator, denominator);
+        Interval::Ratio {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        }
+    }
+
+    pub fn from_cents(cents: f64) -> Interval {
+        Interval::Cents(cents)
+    }
+
+    /// The 12-TET interval `semitones` wide.
+    pub fn from_semitones(semitones: i32) -> Interval {
+        Interval::Cents(f64::from(semitones) * 100.0)
+    }
+
+    /// Parses a 12-TET interval name such as "P5", "m3", "A4" or "M9" into
+    /// an equal tempered interval. Qualities are `P`erfect, `M`ajor,
+    /// `m`inor, `A`ugmented and `d`iminished.
+    pub fn from_name(name: &str) -> Result<Interval, ParseIntervalError> {
+        let name = name.trim();
+        let mut chars = name.chars();
+        let quality = chars.next().ok_or(ParseIntervalError::Empty)?;
+        let invalid_number = || ParseIntervalError::InvalidNumber(chars.as_str().to_string());
+        let number: i32 = chars
+            .as_str()
+            .parse()
+            .ok()
+            .filter(|number| *number > 0)
+            .ok_or_else(invalid_number)?;
+        let step = ((number - 1) % 7) as usize;
+        let perfect = step == 0 || step == 3 || step == 4;
+        let adjustment = match (quality, perfect) {
+            ('P', true) | ('M', false) => 0,
+            ('m', false) => -1,
+            ('A', _) => 1,
+            ('d', true) => -1,
+            ('d', false) => -2,
+            _ => return Err(ParseIntervalError::InvalidQuality(name.to_string())),
+        };
+        let octaves = (number - 1) / 7;
+        let semitones = octaves
+            .checked_mul(12)
+            .and_then(|semitones| semitones.checked_add(DIATONIC_SEMITONES[step] + adjustment))
+            .ok_or_else(invalid_number)?;
+        Ok(Interval::from_semitones(semitones))
+    }
+
+    /// The name of the nearest 12-TET interval, spelling the tritone as an
+    /// augmented fourth. Descending intervals are named by their size.
+    pub fn name(&self) -> String {
+        let semitones = (self.cents().abs() / 100.0).round() as i32;
+        let (quality, number) = SEMITONE_NAMES[(semitones % 12) as usize];
+        format!("{}{}", quality, number + semitones / 12 * 7)
+    }
+
+    pub fn ratio(&self) -> f64 {
+        match *self {
+            Interval::Ratio {
+                numerator,
+                denominator,
+            } => numerator as f64 / denominator as f64,
+            Interval::Cents(cents) => 2.0f64.powf(cents / 1200.0),
+        }
+    }
+
+    pub fn cents(&self) -> f64 {
+        match *self {
+            Interval::Ratio { .. } => ratio_to_cents(self.ratio()),
+            Interval::Cents(cents) => cents,
+        }
+    }
+
+    /// The interval in the same pitch class within one octave, between a
+    /// unison inclusive and an octave exclusive.
+    pub fn octave_reduce(&self) -> Interval {
+        match *self {
+            Interval::Ratio {
+                mut numerator,
+                mut denominator,
+            } => {
+                while denominator
+                    .checked_mul(2)
+                    .is_some_and(|double| numerator >= double)
+                {
+                    denominator *= 2;
+                }
+                while numerator < denominator {
+                    // Terms too large to double fall back on cents, as in `Add`
+                    numerator = match numerator.checked_mul(2) {
+                        Some(double) => double,
+                        None => return Interval::Cents(self.cents().rem_euclid(1200.0)),
+                    };
+                }
+                Interval::new(numerator, denominator)
+            }
+            Interval::Cents(cents) => Interval::Cents(cents.rem_euclid(1200.0)),
+        }
+    }
+
+    /// The interval that completes this one to an octave, so a 3/2 fifth
+    /// inverts to a 4/3 fourth and a unison to an octave. The interval is
+    /// octave reduced first.
+    pub fn invert(&self) -> Interval {
+        Interval::OCTAVE - self.octave_reduce()
+    }
+
+    /// Each prime in the ratio with its exponent, negative for primes in the
+    /// denominator, or `None` for intervals that are only known in cents.
+    pub fn prime_factors(&self) -> Option<Vec<(u64, i32)>> {
+        match *self {
+            Interval::Ratio {
+                numerator,
+                denominator,
+            } => {
+                let mut factors = factorise(numerator);
+                factors.extend(
+                    factorise(denominator)
+                        .into_iter()
+                        .map(|(prime, exponent)| (prime, -exponent)),
+                );
+                factors.sort_by_key(|(prime, _)| *prime);
+                Some(factors)
+            }
+            Interval::Cents(_) => None,
+        }
+    }
+
+    /// The largest prime in the ratio, so 5/4 is a 5-limit interval. A unison
+    /// is 1-limit.
+    pub fn prime_limit(&self) -> Option<u64> {
+        self.prime_factors()
+            .map(|factors| factors.last().map_or(1, |(prime, _)| *prime))
+    }
+}
+
+impl Add for Interval {
+    type Output = Interval;
+
+    /// Stacks two intervals. The result stays exact when both are ratios
+    /// unless the terms would overflow.
+    fn add(self, other: Interval) -> Interval {
+        if let (
+            Interval::Ratio {
+                numerator: a,
+                denominator: b,
+            },
+            Interval::Ratio {
+                numerator: c,
+                denominator: d,
+            },
+        ) = (self, other)
+        {
+            let (ad, cb) = (gcd(a, d), gcd(c, b));
+            if let (Some(numerator), Some(denominator)) =
+                ((a / ad).checked_mul(c / cb), (b / cb).checked_mul(d / ad))
+            {
+                return Interval::Ratio {
+                    numerator,
+                    denominator,
+                };
+            }
+        }
+        Interval::Cents(self.cents() + other.cents())
+    }
+}
+
+impl Sub for Interval {
+    type Output = Interval;
+
+    fn sub(self, other: Interval) -> Interval {
+        let other = match other {
+            Interval::Ratio {
+                numerator,
+                denominator,
+            } => Interval::Ratio {
+                numerator: denominator,
+                denominator: numerator,
+            },
+            Interval::Cents(cents) => Interval::Cents(-cents),
+        };
+        self + other
+    }
+}
+
+/// Treats a bare float as a frequency ratio, as `Pitch::apply_interval` used
+/// to take.
+impl From<f64> for Interval {
+    fn from(ratio: f64) -> Interval {
+        Interval::Cents(ratio_to_cents(ratio))
+    }
+}
+
+impl FromStr for Interval {
+    type Err = ParseIntervalError;
+
+    fn from_str(s: &str) -> Result<Interval, ParseIntervalError> {
+        Interval::from_name(s)
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Interval::Ratio {
+                numerator,
+                denominator,
+            } => write!(f, "{}/{}", numerator, denominator),
+            Interval::Cents(cents) => write!(f, "{:.2}c", cents),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseIntervalError {
+    Empty,
+    InvalidQuality(String),
+    InvalidNumber(String),
+}
+
+impl fmt::Display for ParseIntervalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseIntervalError::Empty => write!(f, "empty interval name"),
+            ParseIntervalError::InvalidQuality(s) => write!(f, "invalid quality in '{}'", s),
+            ParseIntervalError::InvalidNumber(s) => write!(f, "invalid interval number '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseIntervalError {}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+fn factorise(mut n: u64) -> Vec<(u64, i32)> {
+    let mut factors = vec![];
+    let mut prime = 2;
+    while prime <= n / prime {
+        let mut exponent = 0;
+        while n.is_multiple_of(prime) {
+            n /= prime;
+            exponent += 1;
+        }
+        if exponent > 0 {
+            factors.push((prime, exponent));
+        }
+        // Only 2 and odd numbers can be prime
+        prime += if prime == 2 { 1 } else { 2 };
+    }
+    if n > 1 {
+        factors.push((n, 1));
+    }
+    factors
+}
@@ -0,0 +1,773 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::chord::{ChordSymbol, Quality};
+use super::synth::Note;
+use super::{midi_note, parse_pitch_class, Chord, Pitch, Scale};
+
+const LETTERS: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+// Semitones above C of each natural letter.
+const NATURALS: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+const NUMERALS: [&str; 7] = ["I", "II", "III", "IV", "V", "VI", "VII"];
+// The numeral degree of each semitone above the tonic outside the key.
+const CHROMATIC_DEGREES: [usize; 12] = [0, 1, 1, 2, 2, 3, 3, 4, 5, 5, 6, 6];
+// Krumhansl and Kessler's ratings of how well each pitch class, counted up
+// from the tonic, fits a major or minor key.
+const MAJOR_PROFILE: [f64; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f64; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// A tonal centre: a scale starting on a tonic. For seven note scales the
+/// key also knows which letter its tonic is spelled with, so F# major and Gb
+/// major sound alike but spell their notes differently.
+#[derive(Clone, Debug)]
+pub struct Key {
+    pub tonic: Pitch,
+    pub scale: Scale,
+    // Index into LETTERS of the tonic's letter.
+    tonic_letter: usize,
+}
+
+impl Key {
+    /// A key whose tonic is spelled with whichever letter needs the fewest
+    /// accidentals across the scale.
+    pub fn new(tonic: Pitch, scale: Scale) -> Key {
+        let tonic_key = tonic.nearest_midi_note().0;
+        let mut key = Key {
+            tonic,
+            scale,
+            tonic_letter: 0,
+        };
+        key.tonic_letter = (0..7)
+            .filter(|letter| accidental(*letter, tonic_key).abs() <= 1)
+            .min_by_key(|letter| {
+                key.tonic_letter = *letter;
+                key.signature_accidentals()
+                    .iter()
+                    .map(|a| a.abs())
+                    .sum::<i32>()
+            })
+            .unwrap_or(0);
+        key
+    }
+
+    pub fn major(tonic: Pitch) -> Key {
+        Key::new(tonic, Scale::named("major").unwrap())
+    }
+
+    pub fn minor(tonic: Pitch) -> Key {
+        Key::new(tonic, Scale::named("minor").unwrap())
+    }
+
+    /// Number of sharps in the key signature, negative for flats. Only
+    /// meaningful for seven note scales.
+    pub fn signature(&self) -> i32 {
+        self.signature_accidentals().iter().sum()
+    }
+
+    fn signature_accidentals(&self) -> Vec<i32> {
+        if self.scale.len() != 7 {
+            return vec![];
+        }
+        (0..7)
+            .map(|degree| {
+                let key = self.degree_pitch(degree).nearest_midi_note().0;
+                accidental(self.letter(degree), key)
+            })
+            .collect()
+    }
+
+    fn letter(&self, degree: i32) -> usize {
+        (self.tonic_letter + degree.rem_euclid(7) as usize) % 7
+    }
+
+    pub fn degree_pitch(&self, degree: i32) -> Pitch {
+        self.scale.pitch(&self.tonic, degree)
+    }
+
+    /// Moves `pitch` by `steps` degrees of the scale. Pitches between scale
+    /// tones keep their offset from the nearest one, so a raised leading tone
+    /// stays raised.
+    pub fn transpose_pitch(&self, pitch: &Pitch, steps: i32) -> Pitch {
+        let degree = self.scale.nearest_degree(&self.tonic, pitch);
+        let offset = self.degree_pitch(degree).cents_to(pitch);
+        self.degree_pitch(degree + steps).offset_cents(offset)
+    }
+
+    pub fn transpose_note(&self, note: &Note, steps: i32) -> Note {
+        Note {
+            pitch: self.transpose_pitch(&note.pitch, steps),
+            ..note.clone()
+        }
+    }
+
+    pub fn transpose_chord(&self, chord: &Chord, steps: i32) -> Chord {
+        Chord(
+            chord
+                .0
+                .iter()
+                .map(|pitch| self.transpose_pitch(pitch, steps))
+                .collect(),
+        )
+    }
+
+    /// The key with the same scale on `degree` of this one.
+    pub fn modulate(&self, degree: i32) -> Key {
+        self.with_tonic_on(degree, self.scale.clone())
+    }
+
+    pub fn dominant(&self) -> Key {
+        self.modulate(4)
+    }
+
+    pub fn subdominant(&self) -> Key {
+        self.modulate(3)
+    }
+
+    /// The key on the same tonic with a different scale.
+    pub fn parallel(&self, scale: Scale) -> Key {
+        Key::new(self.tonic, scale)
+    }
+
+    /// The relative minor of a major key or relative major of a minor one.
+    pub fn relative(&self) -> Key {
+        if self.triad_is_major(0) {
+            self.with_tonic_on(5, Scale::named("minor").unwrap())
+        } else {
+            self.with_tonic_on(2, Scale::named("major").unwrap())
+        }
+    }
+
+    /// Keys whose tonic triad is a major or minor triad of this key, that is
+    /// the keys one sharp or flat away and their relatives.
+    pub fn related_keys(&self) -> Vec<Key> {
+        (1..self.scale.len() as i32)
+            .filter_map(|degree| {
+                let third = self
+                    .degree_pitch(degree)
+                    .cents_to(&self.degree_pitch(degree + 2));
+                let fifth = self
+                    .degree_pitch(degree)
+                    .cents_to(&self.degree_pitch(degree + 4));
+                if (fifth - 700.0).abs() > 50.0 {
+                    return None;
+                }
+                let scale = if self.triad_is_major(degree) {
+                    "major"
+                } else if (third - 300.0).abs() < 50.0 {
+                    "minor"
+                } else {
+                    return None;
+                };
+                Some(self.with_tonic_on(degree, Scale::named(scale).unwrap()))
+            })
+            .collect()
+    }
+
+    fn triad_is_major(&self, degree: i32) -> bool {
+        let third = self
+            .degree_pitch(degree)
+            .cents_to(&self.degree_pitch(degree + 2));
+        (third - 400.0).abs() < 50.0
+    }
+
+    fn with_tonic_on(&self, degree: i32, scale: Scale) -> Key {
+        let tonic = self.degree_pitch(degree);
+        if self.scale.len() != 7 {
+            return Key::new(tonic, scale);
+        }
+        Key {
+            tonic,
+            scale,
+            tonic_letter: self.letter(degree),
+        }
+    }
+
+    /// Builds the chord for a roman numeral such as "I", "ii7", "V/V",
+    /// "viio7", "bVII", "V65" or "iiø43". Case gives the quality of the
+    /// triad, `o`, `ø` and `+` make it diminished, half diminished or
+    /// augmented, `maj7` asks for a major seventh and figured bass numbers
+    /// (6, 64, 65, 43, 42) invert it. Degrees count along the major scale on
+    /// the tonic, so in a minor key "bIII", "bVI" and "bVII" are diatonic
+    /// while "V" and "viio" sit on the leading tone and "I" and "IV" borrow
+    /// from the parallel major.
+    pub fn chord(&self, numeral: &str) -> Result<Chord, ParseNumeralError> {
+        let numeral = numeral.trim();
+        if let Some(slash) = numeral.find('/') {
+            // V/ii/IV is the dominant of the supertonic of the subdominant
+            let mut key = self.clone();
+            for target in numeral[slash + 1..].rsplit('/') {
+                let (root, upper, rest) = parse_numeral(target)?;
+                if !rest.is_empty() {
+                    return Err(ParseNumeralError::InvalidNumeral(target.to_string()));
+                }
+                key = key.tonicised(root, upper);
+            }
+            return key.chord(&numeral[..slash]);
+        }
+        let (root, upper, rest) = parse_numeral(numeral)?;
+        let invalid = || ParseNumeralError::InvalidFigure(numeral.to_string());
+        let mut third = if upper { 4 } else { 3 };
+        let mut fifth = 7;
+        let mut seventh = 10;
+        let mut rest = rest;
+        if let Some(after) = strip(rest, &["o", "°"]) {
+            third = 3;
+            fifth = 6;
+            seventh = 9;
+            rest = after;
+        } else if let Some(after) = strip(rest, &["ø", "%"]) {
+            third = 3;
+            fifth = 6;
+            seventh = 10;
+            rest = after;
+        } else if let Some(after) = strip(rest, &["+"]) {
+            third = 4;
+            fifth = 8;
+            rest = after;
+        }
+        if let Some(after) = strip(rest, &["maj", "M"]) {
+            seventh = 11;
+            rest = after;
+        }
+        let (has_seventh, inversion) = match rest {
+            "" => (false, 0),
+            "6" => (false, 1),
+            "64" => (false, 2),
+            "7" => (true, 0),
+            "65" => (true, 1),
+            "43" => (true, 2),
+            "42" | "2" => (true, 3),
+            _ => return Err(invalid()),
+        };
+        let root_pitch = self.root_pitch(root);
+        let mut tones = vec![0, third, fifth];
+        if has_seventh {
+            tones.push(seventh);
+        }
+        let chord = Chord(
+            tones
+                .iter()
+                .map(|tone| root_pitch.offset_cents(f64::from(*tone) * 100.0))
+                .collect(),
+        );
+        Ok(chord.inversion(inversion))
+    }
+
+    /// The roman numeral for `symbol` in this key, the inverse of `chord`.
+    /// Chords diatonic to the key or borrowed from its parallel major or
+    /// minor are named by their root, such as "bVI" in C major or "IV" in A
+    /// minor. Other chromatic chords are named as the dominant or leading
+    /// tone chord of a scale degree when they can be, such as "V7/V", and
+    /// otherwise by their altered root, such as "bII". Extensions are
+    /// ignored. Returns `None` for suspended and power chords and for keys
+    /// that aren't based on seven note scales.
+    pub fn numeral(&self, symbol: &ChordSymbol) -> Option<String> {
+        if self.scale.len() != 7 || symbol.quality.third().is_none() {
+            return None;
+        }
+        let classes: Vec<i32> = (0..7).map(|degree| self.degree_class(degree)).collect();
+        let degree_of = |class: i32| {
+            classes
+                .iter()
+                .position(|c| *c == class.rem_euclid(12))
+                .map(|degree| degree as i32)
+        };
+        let tones: Vec<i32> = symbol
+            .tones()
+            .iter()
+            .map(|tone| symbol.root + tone)
+            .chain(symbol.bass)
+            .collect();
+        let diatonic = ["major", "minor"]
+            .iter()
+            .map(|scale| self.parallel(Scale::named(scale).unwrap()))
+            .chain(Some(self.clone()))
+            .any(|key| {
+                let classes: Vec<i32> = (0..7).map(|degree| key.degree_class(degree)).collect();
+                tones
+                    .iter()
+                    .all(|class| classes.contains(&class.rem_euclid(12)))
+            });
+        if diatonic {
+            let (accidentals, degree) = self.roman(symbol.root);
+            return Some(accidentals + &numeral_name(NUMERALS[degree], symbol));
+        }
+
+        let secondary = match (symbol.quality, symbol.seventh) {
+            (Quality::Major, None) | (Quality::Major, Some(10)) => Some((7, "V")),
+            (Quality::Diminished, None)
+            | (Quality::Diminished, Some(9))
+            | (Quality::Diminished, Some(10)) => Some((11, "VII")),
+            _ => None,
+        };
+        if let Some((above, roman)) = secondary {
+            let target = degree_of(symbol.root - above).filter(|degree| {
+                let fifth = self
+                    .degree_pitch(*degree)
+                    .cents_to(&self.degree_pitch(degree + 4));
+                *degree != 0 && (fifth - 700.0).abs() < 50.0
+            });
+            if let Some(target) = target {
+                let (accidentals, degree) = self.roman(classes[target as usize]);
+                let target_name = if self.triad_is_major(target) {
+                    NUMERALS[degree].to_string()
+                } else {
+                    NUMERALS[degree].to_lowercase()
+                };
+                return Some(format!(
+                    "{}/{}{}",
+                    numeral_name(roman, symbol),
+                    accidentals,
+                    target_name
+                ));
+            }
+        }
+
+        let (accidentals, degree) = self.roman(symbol.root);
+        Some(accidentals + &numeral_name(NUMERALS[degree], symbol))
+    }
+
+    // The degree of the major scale on the tonic that names a pitch class
+    // in a numeral, with the accidentals that alter it, such as ("b", 2) for
+    // C in A minor. The key's own degrees keep their number and other notes
+    // take the usual bII, bIII, #IV, bVI and bVII.
+    fn roman(&self, class: i32) -> (String, usize) {
+        let tonic_class = self.degree_class(0);
+        let step = (class - tonic_class).rem_euclid(12);
+        let degree = (0..7)
+            .find(|degree| self.degree_class(*degree as i32) == class.rem_euclid(12))
+            .unwrap_or(CHROMATIC_DEGREES[step as usize]);
+        let alteration = (step - NATURALS[degree] + 6).rem_euclid(12) - 6;
+        let accidental = if alteration > 0 { "#" } else { "b" };
+        (
+            accidental.repeat(alteration.unsigned_abs() as usize),
+            degree,
+        )
+    }
+
+    // The pitch class of a scale degree, in semitones above C.
+    fn degree_class(&self, degree: i32) -> i32 {
+        self.degree_pitch(degree)
+            .nearest_midi_note()
+            .0
+            .rem_euclid(12)
+    }
+
+    // The pitch of a numeral's root, a degree of the major scale on the
+    // tonic possibly raised or lowered by semitones. The key's own degree is
+    // moved onto it rather than the tonic so that microtonal scales keep
+    // their tuning where they agree with the major scale.
+    fn root_pitch(&self, (degree, alteration): (i32, i32)) -> Pitch {
+        let pitch = self.degree_pitch(degree);
+        // NATURALS doubles as the major scale counted up from C
+        let own = (self.tonic.cents_to(&pitch) / 100.0).round() as i32;
+        pitch.offset_cents(f64::from(NATURALS[degree as usize] + alteration - own) * 100.0)
+    }
+
+    // The major or minor key on a numeral's root, for secondary chords.
+    fn tonicised(&self, (degree, alteration): (i32, i32), upper: bool) -> Key {
+        let scale = if upper { "major" } else { "minor" };
+        let mut key = self.with_tonic_on(degree, Scale::named(scale).unwrap());
+        key.tonic = self.root_pitch((degree, alteration));
+        key
+    }
+
+    /// The name of `pitch` as spelled in this key, such as "F#4" in G major
+    /// or "Gb4" in Db major. Notes outside the scale take the spelling with
+    /// the fewest accidentals and then whichever lies closest to the tonic on
+    /// the line of fifths, so C major has Bb and F#, A minor has G# and Bb
+    /// and Gb major has D rather than Ebb. Keys that aren't based on seven note
+    /// scales spell everything with sharps.
+    pub fn spell(&self, pitch: &Pitch) -> String {
+        let (key, _) = pitch.nearest_midi_note();
+        if self.scale.len() != 7 {
+            return pitch.name();
+        }
+        let degree = self.scale.nearest_degree(&self.tonic, pitch);
+        let scale_key = self.degree_pitch(degree).nearest_midi_note().0;
+        let letter = if key == scale_key {
+            self.letter(degree)
+        } else {
+            let tonic_letter = self.letter(0);
+            let tonic_key = self.tonic.nearest_midi_note().0;
+            let centre = fifths(tonic_letter, accidental(tonic_letter, tonic_key)) * 2 + 1;
+            (degree - 1..=degree + 1)
+                .map(|degree| self.letter(degree))
+                .min_by_key(|letter| {
+                    let accidental = accidental(*letter, key);
+                    (
+                        accidental.abs(),
+                        (fifths(*letter, accidental) * 2 - centre).abs(),
+                    )
+                })
+                .unwrap()
+        };
+        let accidental = accidental(letter, key);
+        if accidental.abs() > 2 {
+            return pitch.name();
+        }
+        format!(
+            "{}{}",
+            spelling(letter, accidental),
+            (key - accidental).div_euclid(12) - 1
+        )
+    }
+
+    /// Ranks the 24 major and minor keys by how well the time each pitch
+    /// class sounds in `notes` correlates with its profile for the key, in
+    /// the manner of Krumhansl and Schmuckler. The best match comes first
+    /// with correlations between -1 and 1. Tonics are placed in octave 4
+    /// and pitches are read as their nearest 12-TET pitch class. Returns an
+    /// empty list when nothing sounds.
+    pub fn rank(notes: &[Note]) -> Vec<(Key, f64)> {
+        let mut durations = [0.0; 12];
+        for note in notes {
+            durations[pitch_class(&note.pitch)] += note.duration;
+        }
+        rank_profile(&durations)
+    }
+
+    /// Like `rank` but counting each pitch once, for collections without
+    /// durations.
+    pub fn rank_pitches(pitches: &[Pitch]) -> Vec<(Key, f64)> {
+        let mut counts = [0.0; 12];
+        for pitch in pitches {
+            counts[pitch_class(pitch)] += 1.0;
+        }
+        rank_profile(&counts)
+    }
+
+    /// The most likely key of `notes`, see `rank`.
+    pub fn detect(notes: &[Note]) -> Option<Key> {
+        Key::rank(notes).into_iter().next().map(|(key, _)| key)
+    }
+
+    /// Ranks keys over windows of `notes` `window` seconds long, starting
+    /// every `hop` seconds from the first onset, to follow modulations. Each
+    /// window's start is returned with its ranking, counting only the part
+    /// of each note inside the window. Windows where nothing sounds have
+    /// empty rankings.
+    pub fn rank_windows(notes: &[Note], window: f64, hop: f64) -> Vec<(f64, Vec<(Key, f64)>)> {
+        assert!(
+            window > 0.0 && hop > 0.0,
+            "windows must have a positive length and hop"
+        );
+        let first = notes
+            .iter()
+            .map(|note| note.onset)
+            .fold(f64::INFINITY, f64::min);
+        let last = notes
+            .iter()
+            .map(|note| note.onset + note.duration)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let mut windows = vec![];
+        let mut start = first;
+        while start < last {
+            let end = start + window;
+            let mut durations = [0.0; 12];
+            for note in notes {
+                let overlap = (note.onset + note.duration).min(end) - note.onset.max(start);
+                if overlap > 0.0 {
+                    durations[pitch_class(&note.pitch)] += overlap;
+                }
+            }
+            windows.push((start, rank_profile(&durations)));
+            start += hop;
+        }
+        windows
+    }
+
+    /// Like `spell` but without the octave.
+    pub fn spell_class(&self, pitch: &Pitch) -> String {
+        self.spell(pitch)
+            .trim_end_matches(|c: char| c.is_ascii_digit() || c == '-')
+            .to_string()
+    }
+}
+
+// Semitones the MIDI `key` lies above the natural `letter` nearest to it.
+fn accidental(letter: usize, key: i32) -> i32 {
+    (key - NATURALS[letter] + 6).rem_euclid(12) - 6
+}
+
+// Position of a spelled note on the line of fifths, with C at zero.
+fn fifths(letter: usize, accidental: i32) -> i32 {
+    [0, 2, 4, -1, 1, 3, 5][letter] + accidental * 7
+}
+
+fn spelling(letter: usize, accidental: i32) -> String {
+    let mut name = LETTERS[letter].to_string();
+    let symbol = if accidental > 0 { "#" } else { "b" };
+    name.push_str(&symbol.repeat(accidental.unsigned_abs() as usize));
+    name
+}
+
+fn pitch_class(pitch: &Pitch) -> usize {
+    pitch.nearest_midi_note().0.rem_euclid(12) as usize
+}
+
+fn rank_profile(weights: &[f64; 12]) -> Vec<(Key, f64)> {
+    if weights.iter().all(|weight| *weight == 0.0) {
+        return vec![];
+    }
+    let mut ranking = vec![];
+    for tonic in 0..12 {
+        for (profile, scale) in &[(MAJOR_PROFILE, "major"), (MINOR_PROFILE, "minor")] {
+            let rotated: Vec<f64> = (0..12)
+                .map(|class| profile[(class + 12 - tonic) % 12])
+                .collect();
+            let key = Key::new(
+                Pitch::from_midi(60.0 + tonic as f64),
+                Scale::named(scale).unwrap(),
+            );
+            ranking.push((key, correlation(weights, &rotated)));
+        }
+    }
+    ranking.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    ranking
+}
+
+// Pearson's correlation coefficient, zero when either side is constant.
+fn correlation(a: &[f64], b: &[f64]) -> f64 {
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let mut covariance = 0.0;
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a).powi(2);
+        variance_b += (y - mean_b).powi(2);
+    }
+    if variance_a == 0.0 || variance_b == 0.0 {
+        0.0
+    } else {
+        covariance / (variance_a * variance_b).sqrt()
+    }
+}
+
+// Writes the numeral `roman` with the case, quality and figures that
+// `Key::chord` reads back as `symbol`.
+fn numeral_name(roman: &str, symbol: &ChordSymbol) -> String {
+    let mut name = match symbol.quality {
+        Quality::Major | Quality::Augmented => roman.to_uppercase(),
+        _ => roman.to_lowercase(),
+    };
+    name.push_str(match (symbol.quality, symbol.seventh) {
+        (Quality::Diminished, Some(10)) => "ø",
+        (Quality::Diminished, _) => "o",
+        (Quality::Augmented, _) => "+",
+        _ => "",
+    });
+    if symbol.seventh == Some(11) {
+        name.push_str("maj");
+    }
+    let tones = [
+        Some(0),
+        symbol.quality.third(),
+        symbol.fifth,
+        symbol.seventh,
+    ];
+    let inversion = symbol
+        .bass
+        .and_then(|bass| {
+            let bass = (bass - symbol.root).rem_euclid(12);
+            tones.iter().position(|tone| *tone == Some(bass))
+        })
+        .unwrap_or(0);
+    let figures: &[&str] = if symbol.seventh.is_some() {
+        &["7", "65", "43", "42"]
+    } else {
+        &["", "6", "64"]
+    };
+    name.push_str(figures.get(inversion).unwrap_or(&""));
+    name
+}
+
+// Splits an optionally altered roman numeral off the front of `numeral`,
+// returning the (scale degree, semitone alteration), whether it was upper
+// case and what follows it.
+fn parse_numeral(numeral: &str) -> Result<((i32, i32), bool, &str), ParseNumeralError> {
+    let invalid = || ParseNumeralError::InvalidNumeral(numeral.to_string());
+    let body = numeral.trim_start_matches(&['b', '#'][..]);
+    let alteration = numeral[..numeral.len() - body.len()]
+        .chars()
+        .map(|c| if c == '#' { 1 } else { -1 })
+        .sum();
+    let length = body.len() - body.trim_start_matches(&['I', 'V', 'i', 'v'][..]).len();
+    let roman = &body[..length];
+    let degree = NUMERALS
+        .iter()
+        .position(|candidate| candidate.eq_ignore_ascii_case(roman))
+        .ok_or_else(invalid)?;
+    let upper = roman.chars().all(|c| c.is_ascii_uppercase());
+    if !upper && !roman.chars().all(|c| c.is_ascii_lowercase()) {
+        return Err(invalid());
+    }
+    Ok(((degree as i32, alteration), upper, &body[length..]))
+}
+
+fn strip<'a>(text: &'a str, prefixes: &[&str]) -> Option<&'a str> {
+    prefixes
+        .iter()
+        .find(|prefix| text.starts_with(*prefix))
+        .map(|prefix| &text[prefix.len()..])
+}
+
+impl FromStr for Key {
+    type Err = ParseKeyError;
+
+    /// Parses a tonic and scale name such as "C major", "F# minor" or
+    /// "Eb3 dorian". Tonics without an octave are placed in octave 4.
+    fn from_str(s: &str) -> Result<Key, ParseKeyError> {
+        let s = s.trim();
+        let (tonic, scale) = s.split_at(s.find(char::is_whitespace).unwrap_or(s.len()));
+        let invalid_tonic = || ParseKeyError::InvalidTonic(tonic.to_string());
+        let scale = Scale::named(scale.trim())
+            .ok_or_else(|| ParseKeyError::UnknownScale(scale.trim().to_string()))?;
+        let (pitch_class, octave) = parse_pitch_class(tonic).map_err(|_| invalid_tonic())?;
+        let octave: i32 = if octave.is_empty() {
+            4
+        } else {
+            octave.parse().map_err(|_| invalid_tonic())?
+        };
+        let note = midi_note(pitch_class, octave).ok_or_else(invalid_tonic)?;
+        let tonic_pitch = Pitch::from_midi(f64::from(note));
+        let first = tonic.chars().next().map(|c| c.to_ascii_uppercase());
+        let letter = LETTERS
+            .iter()
+            .position(|letter| Some(*letter) == first)
+            .ok_or_else(invalid_tonic)?;
+        let mut key = Key::new(tonic_pitch, scale);
+        if key.scale.len() == 7 {
+            key.tonic_letter = letter;
+        }
+        Ok(key)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseKeyError {
+    InvalidTonic(String),
+    UnknownScale(String),
+}
+
+impl fmt::Display for ParseKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseKeyError::InvalidTonic(s) => write!(f, "invalid tonic '{}'", s),
+            ParseKeyError::UnknownScale(s) => write!(f, "unknown scale '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseKeyError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseNumeralError {
+    InvalidNumeral(String),
+    InvalidFigure(String),
+}
+
+impl fmt::Display for ParseNumeralError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseNumeralError::InvalidNumeral(s) => write!(f, "invalid roman numeral '{}'", s),
+            ParseNumeralError::InvalidFigure(s) => write!(f, "invalid figures in '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseNumeralError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuning::Tuning;
+
+    fn spellings(key: &str) -> Vec<String> {
+        let key: Key = key.parse().unwrap();
+        (60..72)
+            .map(|note| key.spell_class(&Pitch::from_midi(f64::from(note))))
+            .collect()
+    }
+
+    #[test]
+    fn chromatic_notes_avoid_double_accidentals() {
+        let expected = [
+            ("Gb major", "C Db D Eb E F Gb G Ab A Bb Cb"),
+            ("Cb major", "C Db D Eb Fb F Gb G Ab A Bb Cb"),
+            ("Eb minor", "C Db D Eb E F Gb G Ab A Bb Cb"),
+            ("F# major", "C C# D D# E E# F# G G# A A# B"),
+            ("C# major", "B# C# D D# E E# F# G G# A A# B"),
+            ("D# minor", "C C# D D# E E# F# G G# A A# B"),
+        ];
+        for (key, names) in expected.iter() {
+            assert_eq!(spellings(key).join(" "), *names, "{}", key);
+        }
+    }
+
+    #[test]
+    fn chromatic_notes_lean_towards_the_tonic() {
+        assert_eq!(
+            spellings("C major").join(" "),
+            "C Db D Eb E F F# G Ab A Bb B"
+        );
+        assert_eq!(
+            spellings("A minor").join(" "),
+            "C C# D D# E F F# G G# A Bb B"
+        );
+    }
+
+    fn classes(chord: &Chord) -> Vec<i32> {
+        chord
+            .0
+            .iter()
+            .map(|pitch| pitch.nearest_midi_note().0.rem_euclid(12))
+            .collect()
+    }
+
+    fn renamed(key: &Key, numeral: &str) -> (Chord, Option<String>) {
+        let chord = key.chord(numeral).unwrap();
+        let symbol = chord.symbol(&Tuning::equal(12)).unwrap();
+        (chord, key.numeral(&symbol))
+    }
+
+    #[test]
+    fn numerals_name_the_chords_they_build() {
+        let major = "I ii iii IV V vi viio V7 ii7 V65 I64 Imaj7 bIII iv v bVI bVII iio bII6 \
+                     V/V V7/IV V/ii V7/vi viio7/V";
+        let minor = "i iio bIII iv v V V7 bVI bVII viio viio7 I IV ii vi bII6 V/v V7/iv";
+        for (keys, numerals) in [
+            (["C major", "G major", "Gb major", "C# major"], major),
+            (["A minor", "Eb minor", "F# minor", "Bb minor"], minor),
+        ]
+        .iter()
+        {
+            for key in keys.iter() {
+                let key: Key = key.parse().unwrap();
+                for numeral in numerals.split_whitespace() {
+                    assert_eq!(renamed(&key, numeral).1.as_deref(), Some(numeral));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn numerals_round_trip_to_the_same_chord() {
+        let numerals = "I i II ii III iii IV iv V v VI vi VII vii viio viio7 V/V V/v V7/IV \
+                        V7/iv V/ii V/vi viio7/V #ivo7 bII bIII bVI bVII V/bVI IV/IV";
+        for key in ["C major", "Gb major", "A minor", "Eb minor", "D dorian"].iter() {
+            let key: Key = key.parse().unwrap();
+            for numeral in numerals.split_whitespace() {
+                let (chord, name) = renamed(&key, numeral);
+                let again = key.chord(&name.unwrap()).unwrap();
+                assert_eq!(classes(&again), classes(&chord), "{}", numeral);
+            }
+        }
+    }
+}
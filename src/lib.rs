@@ -1,96 +1,251 @@
+extern crate hound;
+extern crate midly;
 extern crate rand;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
 
+pub mod analysis;
+pub mod chord;
+pub mod harmonisation;
+pub mod interval;
+pub mod key;
+pub mod markov;
+pub mod midi;
+pub mod pitch_class;
+pub mod random;
+pub mod scale;
 pub mod synth;
+pub mod tempo;
+pub mod tuning;
+pub mod voice_leading;
 
-use rand::prelude::*;
+use std::cmp::Ordering;
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
+pub use chord::Chord;
+pub use interval::Interval;
+pub use key::Key;
+pub use pitch_class::PitchClassSet;
+pub use scale::Scale;
+
+/// Frequency of A4 (MIDI note 69) used when no other reference is given.
+pub const CONCERT_A: f64 = 440.0;
+
+pub(crate) const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Pitches are compared, ordered and hashed after rounding to this many
+/// cents.
+pub const PITCH_RESOLUTION_CENTS: f64 = 0.01;
+
+/// A frequency in Hz. Equality, ordering and hashing all go by the pitch
+/// rounded to `PITCH_RESOLUTION_CENTS`, so pitches that differ only by
+/// floating point noise are equal and pitches sort from low to high. Zero,
+/// negative and NaN frequencies are all equal to each other and sort above
+/// every real pitch.
 #[derive(Copy, Clone, Debug)]
 pub struct Pitch(pub f32);
 impl Hash for Pitch {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        ((self.0 * 1000.0) as i32).hash(state);
+        self.quantised().hash(state);
     }
 }
 impl PartialEq for Pitch {
     fn eq(&self, other: &Pitch) -> bool {
-        self.0 == other.0
+        self.quantised() == other.quantised()
     }
 }
 impl Eq for Pitch {}
+impl PartialOrd for Pitch {
+    fn partial_cmp(&self, other: &Pitch) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Pitch {
+    fn cmp(&self, other: &Pitch) -> Ordering {
+        self.quantised().cmp(&other.quantised())
+    }
+}
 
 impl Pitch {
-    pub fn apply_interval(&self, interval: f64) -> Pitch {
-        Pitch(self.0 * interval as f32)
+    /// Transposes by `interval`, which may also be given as a bare frequency
+    /// ratio.
+    pub fn apply_interval<I: Into<Interval>>(&self, interval: I) -> Pitch {
+        Pitch((f64::from(self.0) * interval.into().ratio()) as f32)
     }
-}
 
-#[derive(Copy, Clone, Debug)]
-pub struct Interval(pub f64);
-
-#[derive(Clone, Debug)]
-pub struct Chord(pub Vec<Pitch>);
-impl Chord {
-    pub fn invert(&self) -> Self {
-        let mut chord = self.clone();
-        if chord.0[0].0 < chord.0[1].0 {
-            chord.0[0] = chord.0[0].apply_interval(2.0);
+    /// The interval from this pitch up to `other`.
+    pub fn interval_to(&self, other: &Pitch) -> Interval {
+        Interval::from_cents(self.cents_to(other))
+    }
+
+    /// The 12-TET pitch of a MIDI note number. Fractional note numbers fall
+    /// between keys.
+    pub fn from_midi(note: f64) -> Pitch {
+        Pitch::from_midi_with_reference(note, CONCERT_A)
+    }
+
+    pub fn from_midi_with_reference(note: f64, a4: f64) -> Pitch {
+        Pitch((a4 * 2.0f64.powf((note - 69.0) / 12.0)) as f32)
+    }
+
+    /// The fractional MIDI note number of this pitch.
+    pub fn midi_note(&self) -> f64 {
+        self.midi_note_with_reference(CONCERT_A)
+    }
+
+    pub fn midi_note_with_reference(&self, a4: f64) -> f64 {
+        69.0 + 12.0 * (f64::from(self.0) / a4).log2()
+    }
+
+    /// The nearest MIDI note number and how many cents this pitch lies above
+    /// (or, when negative, below) it.
+    pub fn nearest_midi_note(&self) -> (i32, f64) {
+        let note = self.midi_note();
+        let nearest = note.round();
+        (nearest as i32, (note - nearest) * 100.0)
+    }
+
+    /// Cents from this pitch up to `other`.
+    pub fn cents_to(&self, other: &Pitch) -> f64 {
+        1200.0 * (f64::from(other.0) / f64::from(self.0)).log2()
+    }
+
+    pub fn offset_cents(&self, cents: f64) -> Pitch {
+        Pitch((f64::from(self.0) * 2.0f64.powf(cents / 1200.0)) as f32)
+    }
+
+    /// Whether the pitches lie within `cents` of each other.
+    pub fn approx_eq(&self, other: &Pitch, cents: f64) -> bool {
+        self.cents_to(other).abs() <= cents
+    }
+
+    /// Whether the pitches lie within `cents` of each other once octaves are
+    /// ignored.
+    pub fn same_class(&self, other: &Pitch, cents: f64) -> bool {
+        let difference = self.cents_to(other).rem_euclid(1200.0);
+        difference.min(1200.0 - difference) <= cents
+    }
+
+    /// Cents above the C at or below this pitch, from 0 up to but not
+    /// including 1200.
+    pub fn pitch_class(&self) -> f64 {
+        self.rounded_cents().rem_euclid(1200.0)
+    }
+
+    /// The octave number in scientific pitch notation, where octave 4 runs
+    /// from middle C up to the B above it.
+    pub fn octave(&self) -> i32 {
+        (self.rounded_cents() / 1200.0).floor() as i32 - 1
+    }
+
+    /// The inverse of `pitch_class` and `octave`.
+    pub fn from_class_and_octave(cents: f64, octave: i32) -> Pitch {
+        Pitch::from_midi(f64::from((octave + 1) * 12) + cents / 100.0)
+    }
+
+    // Cents above MIDI note 0 in steps of PITCH_RESOLUTION_CENTS, with every
+    // pitch that has no logarithm mapped above all others.
+    fn quantised(&self) -> i64 {
+        let steps = (self.midi_note() * 100.0 / PITCH_RESOLUTION_CENTS).round();
+        if !steps.is_finite() {
+            i64::MAX
         } else {
-            chord.0[0] = chord.0[0].apply_interval(0.5);
+            steps as i64
         }
-        chord
     }
 
-    pub fn randomize_voicing(&self) -> Self {
-        let mut chord = self.clone();
-        for pitch in chord.0[1..].iter_mut() {
-            if thread_rng().gen::<f32>() > 0.5 {
-                *pitch = pitch.apply_interval(2.0);
-            } else {
-                *pitch = pitch.apply_interval(0.5);
-            }
-        }
-        chord
+    fn rounded_cents(&self) -> f64 {
+        self.quantised() as f64 * PITCH_RESOLUTION_CENTS
+    }
+
+    /// Parses scientific pitch notation such as "A4", "C#3" or "Bb-1" where
+    /// C4 is middle C. Any number of `#` or `b` accidentals may follow the
+    /// letter.
+    pub fn from_name(name: &str) -> Result<Pitch, ParsePitchError> {
+        Pitch::from_name_with_reference(name, CONCERT_A)
+    }
+
+    pub fn from_name_with_reference(name: &str, a4: f64) -> Result<Pitch, ParsePitchError> {
+        let (pitch_class, octave) = parse_pitch_class(name.trim())?;
+        let invalid_octave = || ParsePitchError::InvalidOctave(octave.to_string());
+        let octave: i32 = octave.parse().map_err(|_| invalid_octave())?;
+        let note = midi_note(pitch_class, octave).ok_or_else(invalid_octave)?;
+        Ok(Pitch::from_midi_with_reference(f64::from(note), a4))
+    }
+
+    /// The scientific pitch name of the nearest 12-TET note, spelled with
+    /// sharps.
+    pub fn name(&self) -> String {
+        let (note, _) = self.nearest_midi_note();
+        format!(
+            "{}{}",
+            NOTE_NAMES[note.rem_euclid(12) as usize],
+            note.div_euclid(12) - 1
+        )
     }
 }
 
-pub struct Scale {
-    interval_pattern: Vec<f32>,
+impl FromStr for Pitch {
+    type Err = ParsePitchError;
+
+    fn from_str(s: &str) -> Result<Pitch, ParsePitchError> {
+        Pitch::from_name(s)
+    }
 }
 
-impl Scale {
-    pub fn new(interval_pattern: &[f32]) -> Self {
-        Self {
-            interval_pattern: interval_pattern.iter().cloned().collect(),
-        }
+// Splits a note letter and any accidentals off the front of `name`, returning
+// the semitones above C (which may fall outside 0..12) and the rest of `name`.
+// The MIDI note of `pitch_class` in scientific pitch `octave`, or `None` if
+// it doesn't fit.
+pub(crate) fn midi_note(pitch_class: i32, octave: i32) -> Option<i32> {
+    octave
+        .checked_add(1)?
+        .checked_mul(12)?
+        .checked_add(pitch_class)
+}
+
+pub(crate) fn parse_pitch_class(name: &str) -> Result<(i32, &str), ParsePitchError> {
+    let mut chars = name.chars();
+    let letter = chars.next().ok_or(ParsePitchError::Empty)?;
+    let mut pitch_class = match letter.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return Err(ParsePitchError::InvalidLetter(letter)),
+    };
+    let accidentals = chars.as_str();
+    let rest = accidentals.trim_start_matches(&['#', 'b'][..]);
+    for accidental in accidentals[..accidentals.len() - rest.len()].chars() {
+        pitch_class += if accidental == '#' { 1 } else { -1 };
     }
+    Ok((pitch_class, rest))
+}
 
-    pub fn pitch(&self, base_pitch: &Pitch, degree: i32) -> Pitch {
-        let mut pitch = *base_pitch;
-        let intervals: Vec<f32>;
-        if degree < 0 {
-            intervals = self
-                .interval_pattern
-                .iter()
-                .rev()
-                .map(|i| 1.0 - i)
-                .collect();
-        } else {
-            intervals = self.interval_pattern.iter().map(|i| 1.0 + i).collect();
-        }
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParsePitchError {
+    Empty,
+    InvalidLetter(char),
+    InvalidOctave(String),
+}
 
-        let mut degree = degree.abs();
-        for interval in intervals.iter().cycle() {
-            if degree <= 0 {
-                break;
-            }
-            pitch.0 *= interval;
-            degree -= 1;
+impl fmt::Display for ParsePitchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParsePitchError::Empty => write!(f, "empty pitch name"),
+            ParsePitchError::InvalidLetter(c) => write!(f, "invalid note letter '{}'", c),
+            ParsePitchError::InvalidOctave(s) => write!(f, "invalid octave '{}'", s),
         }
-        pitch
     }
 }
+
+impl std::error::Error for ParsePitchError {}
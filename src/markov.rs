@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use rand::Rng;
+
+use super::analysis::analyse;
+use super::chord::ChordSymbol;
+use super::synth::Note;
+use super::{Key, Pitch};
+
+// Choices tried by `Markov::generate_with` before giving up.
+const BUDGET: usize = 100_000;
+
+/// A variable order Markov model. Training counts what follows every
+/// context of up to `order` items and sampling follows the longest context
+/// that was seen in training, falling back to shorter ones.
+#[derive(Clone, Debug)]
+pub struct Markov<T> {
+    order: usize,
+    // What followed each context, in the order first seen so that sampling
+    // with a seeded generator is reproducible.
+    counts: HashMap<Vec<T>, Vec<(T, usize)>>,
+}
+
+impl<T: Clone + Eq + Hash> Markov<T> {
+    pub fn new(order: usize) -> Markov<T> {
+        Markov {
+            order,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// A model of `order` trained on each of `corpus`.
+    pub fn trained(order: usize, corpus: &[Vec<T>]) -> Markov<T> {
+        let mut model = Markov::new(order);
+        for sequence in corpus {
+            model.train(sequence);
+        }
+        model
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn train(&mut self, sequence: &[T]) {
+        for (i, item) in sequence.iter().enumerate() {
+            for length in 0..=self.order.min(i) {
+                let followers = self
+                    .counts
+                    .entry(sequence[i - length..i].to_vec())
+                    .or_default();
+                match followers.iter_mut().find(|(other, _)| other == item) {
+                    Some((_, count)) => *count += 1,
+                    None => followers.push((item.clone(), 1)),
+                }
+            }
+        }
+    }
+
+    /// What may follow `history` with how often it did in training, using
+    /// the longest context at the end of `history` that was seen.
+    pub fn choices(&self, history: &[T]) -> &[(T, usize)] {
+        (0..=self.order.min(history.len()))
+            .rev()
+            .filter_map(|length| self.counts.get(&history[history.len() - length..]))
+            .next()
+            .map_or(&[], |followers| followers)
+    }
+
+    /// Draws what follows `history`, or `None` if the model is untrained.
+    pub fn next<R: Rng>(&self, history: &[T], rng: &mut R) -> Option<T> {
+        let choices = self.choices(history);
+        weighted_order(choices.to_vec(), rng).into_iter().next()
+    }
+
+    /// Draws a sequence of up to `length` items. It ends early only when the
+    /// model is untrained.
+    pub fn generate<R: Rng>(&self, length: usize, rng: &mut R) -> Vec<T> {
+        let mut sequence = vec![];
+        while sequence.len() < length {
+            match self.next(&sequence, rng) {
+                Some(item) => sequence.push(item),
+                None => break,
+            }
+        }
+        sequence
+    }
+
+    /// Draws a sequence of exactly `length` items in which each item passes
+    /// `allowed`, given the items before it. When no continuation passes the
+    /// longest context is shortened, and when none pass at all the search
+    /// backs up and draws again. Returns `None` if no such sequence turns up.
+    pub fn generate_with<R, F>(&self, length: usize, rng: &mut R, mut allowed: F) -> Option<Vec<T>>
+    where
+        R: Rng,
+        F: FnMut(&[T], &T) -> bool,
+    {
+        let mut sequence = vec![];
+        // The choices still to try after each prefix of `sequence`, the next
+        // one last
+        let mut untried: Vec<Vec<T>> = vec![];
+        let mut budget = BUDGET;
+        while sequence.len() < length {
+            if untried.len() == sequence.len() {
+                let mut choices =
+                    weighted_order(self.allowed_choices(&sequence, &mut allowed), rng);
+                choices.reverse();
+                untried.push(choices);
+            }
+            match untried.last_mut().unwrap().pop() {
+                Some(item) => {
+                    if budget == 0 {
+                        return None;
+                    }
+                    budget -= 1;
+                    sequence.push(item);
+                }
+                None => {
+                    untried.pop();
+                    sequence.pop()?;
+                }
+            }
+        }
+        Some(sequence)
+    }
+
+    // What may follow `sequence` by the longest context with any follower
+    // that passes `allowed`.
+    fn allowed_choices<F>(&self, sequence: &[T], allowed: &mut F) -> Vec<(T, usize)>
+    where
+        F: FnMut(&[T], &T) -> bool,
+    {
+        (0..=self.order.min(sequence.len()))
+            .rev()
+            .filter_map(|context| self.counts.get(&sequence[sequence.len() - context..]))
+            .map(|followers| {
+                followers
+                    .iter()
+                    .filter(|(item, _)| allowed(sequence, item))
+                    .cloned()
+                    .collect::<Vec<(T, usize)>>()
+            })
+            .find(|choices| !choices.is_empty())
+            .unwrap_or_default()
+    }
+}
+
+// Shuffles `choices` so that each comes first with probability in
+// proportion to its count.
+fn weighted_order<T, R: Rng>(mut choices: Vec<(T, usize)>, rng: &mut R) -> Vec<T> {
+    let mut order = vec![];
+    while !choices.is_empty() {
+        let total: usize = choices.iter().map(|(_, count)| count).sum();
+        let mut target = rng.gen_range(0, total);
+        let index = choices
+            .iter()
+            .position(|(_, count)| {
+                if target < *count {
+                    true
+                } else {
+                    target -= count;
+                    false
+                }
+            })
+            .unwrap();
+        order.push(choices.remove(index).0);
+    }
+    order
+}
+
+/// The scale degree of each note in `key`, counting from the key's tonic so
+/// that the octave below it starts at -7 in a seven note scale.
+pub fn degrees(notes: &[Note], key: &Key) -> Vec<i32> {
+    notes
+        .iter()
+        .map(|note| key.scale.nearest_degree(&key.tonic, &note.pitch))
+        .collect()
+}
+
+/// The steps between the degrees of successive notes in `key`.
+pub fn degree_steps(notes: &[Note], key: &Key) -> Vec<i32> {
+    degrees(notes, key)
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .collect()
+}
+
+/// The intervals between successive notes, rounded to semitones.
+pub fn intervals(notes: &[Note]) -> Vec<i32> {
+    notes
+        .windows(2)
+        .map(|pair| (pair[0].pitch.cents_to(&pair[1].pitch) / 100.0).round() as i32)
+        .collect()
+}
+
+/// The length of each note as a whole number of `unit` seconds, at least
+/// one.
+pub fn durations(notes: &[Note], unit: f64) -> Vec<i64> {
+    notes
+        .iter()
+        .map(|note| ((note.duration / unit).round() as i64).max(1))
+        .collect()
+}
+
+/// The chord of each harmonic segment of `notes`, see `analysis::analyse`.
+pub fn chord_symbols(notes: &[Note], key: &Key) -> Vec<ChordSymbol> {
+    analyse(notes, key)
+        .segments
+        .into_iter()
+        .map(|segment| segment.symbol)
+        .collect()
+}
+
+/// Melodies made of steps between scale degrees and note lengths drawn from
+/// two Markov models.
+#[derive(Clone, Debug)]
+pub struct MelodyGenerator {
+    pub steps: Markov<i32>,
+    /// Note lengths in `unit`s.
+    pub durations: Markov<i64>,
+    /// Seconds in a unit of duration.
+    pub unit: f64,
+    /// Every note lies between these pitches, inclusive.
+    pub low: Pitch,
+    pub high: Pitch,
+    pub start_on_tonic: bool,
+    pub end_on_tonic: bool,
+}
+
+impl MelodyGenerator {
+    /// Trains models of `order` on melodies in `key`, taking the range from
+    /// the lowest and highest notes of the corpus. Melodies start and end on
+    /// the tonic.
+    pub fn train(order: usize, corpus: &[Vec<Note>], key: &Key, unit: f64) -> MelodyGenerator {
+        let pitches = || corpus.iter().flatten().map(|note| note.pitch);
+        MelodyGenerator {
+            steps: Markov::trained(
+                order,
+                &corpus
+                    .iter()
+                    .map(|melody| degree_steps(melody, key))
+                    .collect::<Vec<_>>(),
+            ),
+            durations: Markov::trained(
+                order,
+                &corpus
+                    .iter()
+                    .map(|melody| durations(melody, unit))
+                    .collect::<Vec<_>>(),
+            ),
+            unit,
+            low: pitches().min().unwrap_or(key.tonic),
+            high: pitches().max().unwrap_or(key.tonic),
+            start_on_tonic: true,
+            end_on_tonic: true,
+        }
+    }
+
+    /// Draws a melody of `length` notes in `key` for `instrument`, starting
+    /// at time zero. Without `start_on_tonic` the first note is drawn from
+    /// the scale tones in range. Returns `None` when the models can't meet
+    /// the range and tonic constraints.
+    pub fn generate<R: Rng>(
+        &self,
+        key: &Key,
+        length: usize,
+        instrument: usize,
+        rng: &mut R,
+    ) -> Option<Vec<Note>> {
+        if length == 0 {
+            return Some(vec![]);
+        }
+        let in_range = |degree: i32| {
+            let pitch = key.degree_pitch(degree);
+            pitch >= self.low && pitch <= self.high
+        };
+        let low = key.scale.nearest_degree(&key.tonic, &self.low) - 1;
+        let high = key.scale.nearest_degree(&key.tonic, &self.high) + 1;
+        let len = key.scale.len() as i32;
+        let starts: Vec<i32> = (low..=high)
+            .filter(|degree| in_range(*degree))
+            .filter(|degree| {
+                let on_tonic = self.start_on_tonic || (self.end_on_tonic && length == 1);
+                !on_tonic || degree.rem_euclid(len) == 0
+            })
+            .collect();
+        if starts.is_empty() {
+            return None;
+        }
+        let start = starts[rng.gen_range(0, starts.len())];
+
+        let end_on_tonic = self.end_on_tonic;
+        // The degree reached after each prefix of the steps. The search only
+        // pushes and pops steps and asks about every prefix before extending
+        // it, so only the last total can be out of date.
+        let mut totals = vec![start];
+        let steps = self
+            .steps
+            .generate_with(length - 1, rng, |previous: &[i32], step: &i32| {
+                totals.truncate(previous.len().max(1));
+                if let Some(last) = previous.last() {
+                    let total = totals[totals.len() - 1] + last;
+                    totals.push(total);
+                }
+                let degree = totals[previous.len()] + step;
+                in_range(degree)
+                    && (!end_on_tonic || previous.len() + 2 < length || degree.rem_euclid(len) == 0)
+            })?;
+        let durations = self.durations.generate(length, rng);
+        if durations.len() < length {
+            return None;
+        }
+
+        let mut degree = start;
+        let mut onset = 0.0;
+        let mut melody = vec![];
+        for (i, units) in durations.iter().enumerate() {
+            if i > 0 {
+                degree += steps[i - 1];
+            }
+            let duration = *units as f64 * self.unit;
+            melody.push(Note {
+                instrument,
+                pitch: key.degree_pitch(degree),
+                onset,
+                duration,
+                amplitude: 1.0,
+                parameters: vec![],
+            });
+            onset += duration;
+        }
+        Some(melody)
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use midly::num::{u15, u24, u28, u4, u7};
+use midly::{
+    Format, Header, MetaMessage, MidiMessage, PitchBend, Smf, Timing, TrackEvent, TrackEventKind,
+};
+
+use super::synth::Note;
+use super::tempo::TempoMap;
+use super::tuning::Tuning;
+use super::Pitch;
+
+const DEFAULT_MICROS_PER_BEAT: f64 = 500_000.0;
+const TICKS_PER_BEAT: u16 = 480;
+// Semitones either side of the key reached by a full pitch bend. This is the
+// General MIDI default and what most synths assume without an RPN change.
+const BEND_RANGE: f64 = 2.0;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Smf(midly::Error),
+    UnsupportedFormat,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Smf(e) => write!(f, "{}", e),
+            Error::UnsupportedFormat => write!(f, "only format 0 and 1 MIDI files are supported"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<midly::Error> for Error {
+    fn from(e: midly::Error) -> Error {
+        Error::Smf(e)
+    }
+}
+
+pub fn read_midi_file<P: AsRef<Path>>(path: P) -> Result<Vec<Note>, Error> {
+    parse_midi(&std::fs::read(path)?)
+}
+
+pub fn read_midi_file_with_tuning<P: AsRef<Path>>(
+    path: P,
+    tuning: &Tuning,
+) -> Result<Vec<Note>, Error> {
+    parse_midi_with_tuning(&std::fs::read(path)?, tuning)
+}
+
+/// Converts a format 0 or 1 Standard MIDI File into `Note`s sorted by onset.
+/// Each MIDI channel becomes the instrument index of the same number and
+/// note-on velocity is scaled into an amplitude between zero and one.
+pub fn parse_midi(bytes: &[u8]) -> Result<Vec<Note>, Error> {
+    parse_midi_with_tuning(bytes, &Tuning::equal(12))
+}
+
+/// Like `parse_midi` but keys are mapped to pitches through `tuning`'s
+/// keyboard mapping. Notes on unmapped keys are dropped and pitch bends are
+/// applied on top of the tuned pitch as 12-TET semitones.
+pub fn parse_midi_with_tuning(bytes: &[u8], tuning: &Tuning) -> Result<Vec<Note>, Error> {
+    let smf = Smf::parse(bytes)?;
+    if smf.header.format == Format::Sequential {
+        return Err(Error::UnsupportedFormat);
+    }
+
+    let mut tempo_changes = vec![];
+    for track in &smf.tracks {
+        let mut tick = 0;
+        for event in track {
+            tick += u64::from(event.delta.as_int());
+            if let TrackEventKind::Meta(MetaMessage::Tempo(micros)) = event.kind {
+                tempo_changes.push((tick, f64::from(micros.as_int())));
+            }
+        }
+    }
+    let clock = TickClock::new(smf.header.timing, tempo_changes);
+
+    let mut notes = vec![];
+    for track in &smf.tracks {
+        let mut tick = 0;
+        let mut held: HashMap<(u8, u8), Vec<HeldNote>> = HashMap::new();
+        let mut bends = [0.0; 16];
+        for event in track {
+            tick += u64::from(event.delta.as_int());
+            if let TrackEventKind::Midi { channel, message } = event.kind {
+                let channel = channel.as_int();
+                match message {
+                    MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                        let bend = bends[usize::from(channel)] * 100.0;
+                        if let Some(pitch) = tuning.key_pitch(i32::from(key.as_int())) {
+                            held.entry((channel, key.as_int()))
+                                .or_default()
+                                .push(HeldNote {
+                                    onset: tick,
+                                    pitch: pitch.offset_cents(bend),
+                                    amplitude: f64::from(vel.as_int()) / 127.0,
+                                });
+                        }
+                    }
+                    MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                        let started = held
+                            .get_mut(&(channel, key.as_int()))
+                            .filter(|started| !started.is_empty())
+                            .map(|started| started.remove(0));
+                        if let Some(started) = started {
+                            notes.push(clock.note(channel, &started, tick));
+                        }
+                    }
+                    MidiMessage::PitchBend { bend } => {
+                        bends[usize::from(channel)] = bend.as_f64() * BEND_RANGE;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        for ((channel, _), started) in held {
+            for started in started {
+                notes.push(clock.note(channel, &started, tick));
+            }
+        }
+    }
+    notes.sort_by(|a, b| a.onset.partial_cmp(&b.onset).unwrap());
+    Ok(notes)
+}
+
+pub fn write_midi_file<P: AsRef<Path>>(notes: &[Note], path: P) -> Result<(), Error> {
+    write_midi(notes, std::io::BufWriter::new(std::fs::File::create(path)?))
+}
+
+/// Writes `notes` as a format 1 Standard MIDI File at 120 BPM. Each instrument
+/// index gets its own track and the MIDI channel of the same number (modulo 16).
+/// Pitches are rounded to the nearest key and the remainder is sent as a pitch
+/// bend before the note-on, so overlapping notes on one instrument that need
+/// different bends will share whichever bend came last.
+pub fn write_midi<W: io::Write>(notes: &[Note], out: W) -> Result<(), Error> {
+    let mut header_track = vec![TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(DEFAULT_MICROS_PER_BEAT as u32))),
+    }];
+    header_track.push(end_of_track(0));
+
+    let mut instruments: Vec<usize> = notes.iter().map(|note| note.instrument).collect();
+    instruments.sort();
+    instruments.dedup();
+
+    let mut tracks = vec![header_track];
+    for instrument in instruments {
+        let channel = u4::new((instrument % 16) as u8);
+        // (tick, ordering within a tick, message)
+        let mut messages = vec![];
+        for note in notes.iter().filter(|note| note.instrument == instrument) {
+            let key = note.pitch.midi_note();
+            let nearest = key.round().clamp(0.0, 127.0);
+            let bend = ((key - nearest) / BEND_RANGE).clamp(-1.0, 1.0);
+            let vel = (note.amplitude * 127.0).round().clamp(1.0, 127.0) as u8;
+            let onset = seconds_to_ticks(note.onset);
+            let release = seconds_to_ticks(note.onset + note.duration).max(onset);
+            let key = u7::new(nearest as u8);
+            messages.push((
+                onset,
+                1,
+                MidiMessage::PitchBend {
+                    bend: PitchBend::from_f64(bend),
+                },
+            ));
+            messages.push((
+                onset,
+                2,
+                MidiMessage::NoteOn {
+                    key,
+                    vel: u7::new(vel),
+                },
+            ));
+            messages.push((
+                release,
+                0,
+                MidiMessage::NoteOff {
+                    key,
+                    vel: u7::new(0),
+                },
+            ));
+        }
+        messages.sort_by_key(|(tick, order, _)| (*tick, *order));
+
+        let mut track = vec![];
+        let mut last_tick = 0;
+        let mut bend = PitchBend::from_f64(0.0);
+        for (tick, _, message) in messages {
+            if let MidiMessage::PitchBend { bend: new_bend } = message {
+                if new_bend == bend {
+                    continue;
+                }
+                bend = new_bend;
+            }
+            track.push(TrackEvent {
+                delta: u28::new((tick - last_tick) as u32),
+                kind: TrackEventKind::Midi { channel, message },
+            });
+            last_tick = tick;
+        }
+        track.push(end_of_track(0));
+        tracks.push(track);
+    }
+
+    let smf = Smf {
+        header: Header::new(Format::Parallel, Timing::Metrical(u15::new(TICKS_PER_BEAT))),
+        tracks,
+    };
+    smf.write_std(out)?;
+    Ok(())
+}
+
+fn end_of_track(delta: u32) -> TrackEvent<'static> {
+    TrackEvent {
+        delta: u28::new(delta),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    }
+}
+
+fn seconds_to_ticks(seconds: f64) -> u64 {
+    (seconds.max(0.0) * f64::from(TICKS_PER_BEAT) * 1e6 / DEFAULT_MICROS_PER_BEAT).round() as u64
+}
+
+struct HeldNote {
+    onset: u64,
+    pitch: Pitch,
+    amplitude: f64,
+}
+
+struct TickClock {
+    timing: Timing,
+    tempo: TempoMap,
+}
+
+impl TickClock {
+    fn new(timing: Timing, mut tempo_changes: Vec<(u64, f64)>) -> TickClock {
+        tempo_changes.sort_by_key(|change| change.0);
+        let mut tempo = TempoMap::new(60e6 / DEFAULT_MICROS_PER_BEAT);
+        if let Timing::Metrical(ticks_per_beat) = timing {
+            let ticks_per_beat = f64::from(ticks_per_beat.as_int());
+            for (tick, micros) in tempo_changes {
+                tempo.set_tempo(tick as f64 / ticks_per_beat, 60e6 / micros);
+            }
+        }
+        TickClock { timing, tempo }
+    }
+
+    fn seconds(&self, tick: u64) -> f64 {
+        match self.timing {
+            Timing::Metrical(ticks_per_beat) => self
+                .tempo
+                .seconds_at(tick as f64 / f64::from(ticks_per_beat.as_int())),
+            Timing::Timecode(fps, subframes) => {
+                tick as f64 / (f64::from(fps.as_f32()) * f64::from(subframes))
+            }
+        }
+    }
+
+    fn note(&self, channel: u8, held: &HeldNote, release: u64) -> Note {
+        let onset = self.seconds(held.onset);
+        Note {
+            instrument: usize::from(channel),
+            pitch: held.pitch,
+            onset,
+            duration: self.seconds(release) - onset,
+            amplitude: held.amplitude,
+            parameters: vec![],
+        }
+    }
+}
@@ -0,0 +1,447 @@
+use std::cmp::Ordering;
+
+use super::{Chord, Pitch};
+
+// Forte's names for the set classes of three to six pitch classes in
+// 12-EDO. Larger sets are named after their complements.
+const FORTE: &[(&str, &[i32])] = &[
+    ("3-1", &[0, 1, 2]),
+    ("3-2", &[0, 1, 3]),
+    ("3-3", &[0, 1, 4]),
+    ("3-4", &[0, 1, 5]),
+    ("3-5", &[0, 1, 6]),
+    ("3-6", &[0, 2, 4]),
+    ("3-7", &[0, 2, 5]),
+    ("3-8", &[0, 2, 6]),
+    ("3-9", &[0, 2, 7]),
+    ("3-10", &[0, 3, 6]),
+    ("3-11", &[0, 3, 7]),
+    ("3-12", &[0, 4, 8]),
+    ("4-1", &[0, 1, 2, 3]),
+    ("4-2", &[0, 1, 2, 4]),
+    ("4-3", &[0, 1, 3, 4]),
+    ("4-4", &[0, 1, 2, 5]),
+    ("4-5", &[0, 1, 2, 6]),
+    ("4-6", &[0, 1, 2, 7]),
+    ("4-7", &[0, 1, 4, 5]),
+    ("4-8", &[0, 1, 5, 6]),
+    ("4-9", &[0, 1, 6, 7]),
+    ("4-10", &[0, 2, 3, 5]),
+    ("4-11", &[0, 1, 3, 5]),
+    ("4-12", &[0, 2, 3, 6]),
+    ("4-13", &[0, 1, 3, 6]),
+    ("4-14", &[0, 2, 3, 7]),
+    ("4-Z15", &[0, 1, 4, 6]),
+    ("4-16", &[0, 1, 5, 7]),
+    ("4-17", &[0, 3, 4, 7]),
+    ("4-18", &[0, 1, 4, 7]),
+    ("4-19", &[0, 1, 4, 8]),
+    ("4-20", &[0, 1, 5, 8]),
+    ("4-21", &[0, 2, 4, 6]),
+    ("4-22", &[0, 2, 4, 7]),
+    ("4-23", &[0, 2, 5, 7]),
+    ("4-24", &[0, 2, 4, 8]),
+    ("4-25", &[0, 2, 6, 8]),
+    ("4-26", &[0, 3, 5, 8]),
+    ("4-27", &[0, 2, 5, 8]),
+    ("4-28", &[0, 3, 6, 9]),
+    ("4-Z29", &[0, 1, 3, 7]),
+    ("5-1", &[0, 1, 2, 3, 4]),
+    ("5-2", &[0, 1, 2, 3, 5]),
+    ("5-3", &[0, 1, 2, 4, 5]),
+    ("5-4", &[0, 1, 2, 3, 6]),
+    ("5-5", &[0, 1, 2, 3, 7]),
+    ("5-6", &[0, 1, 2, 5, 6]),
+    ("5-7", &[0, 1, 2, 6, 7]),
+    ("5-8", &[0, 2, 3, 4, 6]),
+    ("5-9", &[0, 1, 2, 4, 6]),
+    ("5-10", &[0, 1, 3, 4, 6]),
+    ("5-11", &[0, 2, 3, 4, 7]),
+    ("5-Z12", &[0, 1, 3, 5, 6]),
+    ("5-13", &[0, 1, 2, 4, 8]),
+    ("5-14", &[0, 1, 2, 5, 7]),
+    ("5-15", &[0, 1, 2, 6, 8]),
+    ("5-16", &[0, 1, 3, 4, 7]),
+    ("5-Z17", &[0, 1, 3, 4, 8]),
+    ("5-Z18", &[0, 1, 4, 5, 7]),
+    ("5-19", &[0, 1, 3, 6, 7]),
+    ("5-20", &[0, 1, 5, 6, 8]),
+    ("5-21", &[0, 1, 4, 5, 8]),
+    ("5-22", &[0, 1, 4, 7, 8]),
+    ("5-23", &[0, 2, 3, 5, 7]),
+    ("5-24", &[0, 1, 3, 5, 7]),
+    ("5-25", &[0, 2, 3, 5, 8]),
+    ("5-26", &[0, 2, 4, 5, 8]),
+    ("5-27", &[0, 1, 3, 5, 8]),
+    ("5-28", &[0, 2, 3, 6, 8]),
+    ("5-29", &[0, 1, 3, 6, 8]),
+    ("5-30", &[0, 1, 4, 6, 8]),
+    ("5-31", &[0, 1, 3, 6, 9]),
+    ("5-32", &[0, 1, 4, 6, 9]),
+    ("5-33", &[0, 2, 4, 6, 8]),
+    ("5-34", &[0, 2, 4, 6, 9]),
+    ("5-35", &[0, 2, 4, 7, 9]),
+    ("5-Z36", &[0, 1, 2, 4, 7]),
+    ("5-Z37", &[0, 3, 4, 5, 8]),
+    ("5-Z38", &[0, 1, 2, 5, 8]),
+    ("6-1", &[0, 1, 2, 3, 4, 5]),
+    ("6-2", &[0, 1, 2, 3, 4, 6]),
+    ("6-Z3", &[0, 1, 2, 3, 5, 6]),
+    ("6-Z4", &[0, 1, 2, 4, 5, 6]),
+    ("6-5", &[0, 1, 2, 3, 6, 7]),
+    ("6-Z6", &[0, 1, 2, 5, 6, 7]),
+    ("6-7", &[0, 1, 2, 6, 7, 8]),
+    ("6-8", &[0, 2, 3, 4, 5, 7]),
+    ("6-9", &[0, 1, 2, 3, 5, 7]),
+    ("6-Z10", &[0, 1, 3, 4, 5, 7]),
+    ("6-Z11", &[0, 1, 2, 4, 5, 7]),
+    ("6-Z12", &[0, 1, 2, 4, 6, 7]),
+    ("6-Z13", &[0, 1, 3, 4, 6, 7]),
+    ("6-14", &[0, 1, 3, 4, 5, 8]),
+    ("6-15", &[0, 1, 2, 4, 5, 8]),
+    ("6-16", &[0, 1, 4, 5, 6, 8]),
+    ("6-Z17", &[0, 1, 2, 4, 7, 8]),
+    ("6-18", &[0, 1, 2, 5, 7, 8]),
+    ("6-Z19", &[0, 1, 3, 4, 7, 8]),
+    ("6-20", &[0, 1, 4, 5, 8, 9]),
+    ("6-21", &[0, 2, 3, 4, 6, 8]),
+    ("6-22", &[0, 1, 2, 4, 6, 8]),
+    ("6-Z23", &[0, 2, 3, 5, 6, 8]),
+    ("6-Z24", &[0, 1, 3, 4, 6, 8]),
+    ("6-Z25", &[0, 1, 3, 5, 6, 8]),
+    ("6-Z26", &[0, 1, 3, 5, 7, 8]),
+    ("6-27", &[0, 1, 3, 4, 6, 9]),
+    ("6-Z28", &[0, 1, 3, 5, 6, 9]),
+    ("6-Z29", &[0, 1, 3, 6, 8, 9]),
+    ("6-30", &[0, 1, 3, 6, 7, 9]),
+    ("6-31", &[0, 1, 3, 5, 8, 9]),
+    ("6-32", &[0, 2, 4, 5, 7, 9]),
+    ("6-33", &[0, 2, 3, 5, 7, 9]),
+    ("6-34", &[0, 1, 3, 5, 7, 9]),
+    ("6-35", &[0, 2, 4, 6, 8, 10]),
+    ("6-Z36", &[0, 1, 2, 3, 4, 7]),
+    ("6-Z37", &[0, 1, 2, 3, 4, 8]),
+    ("6-Z38", &[0, 1, 2, 3, 7, 8]),
+    ("6-Z39", &[0, 2, 3, 4, 5, 8]),
+    ("6-Z40", &[0, 1, 2, 3, 5, 8]),
+    ("6-Z41", &[0, 1, 2, 3, 6, 8]),
+    ("6-Z42", &[0, 1, 2, 3, 6, 9]),
+    ("6-Z43", &[0, 1, 2, 5, 6, 8]),
+    ("6-Z44", &[0, 1, 2, 5, 6, 9]),
+    ("6-Z45", &[0, 2, 3, 4, 6, 9]),
+    ("6-Z46", &[0, 1, 2, 4, 6, 9]),
+    ("6-Z47", &[0, 1, 2, 4, 7, 9]),
+    ("6-Z48", &[0, 1, 2, 5, 7, 9]),
+    ("6-Z49", &[0, 1, 3, 4, 7, 9]),
+    ("6-Z50", &[0, 1, 4, 6, 7, 9]),
+];
+
+/// An unordered set of pitch classes in an equal division of the octave,
+/// counted in steps up from C. Classes are kept reduced and sorted.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PitchClassSet {
+    classes: Vec<i32>,
+    edo: i32,
+}
+
+impl PitchClassSet {
+    pub fn new(classes: &[i32], edo: i32) -> PitchClassSet {
+        assert!(edo > 0, "an octave must have at least one division");
+        let mut classes: Vec<i32> = classes.iter().map(|class| class.rem_euclid(edo)).collect();
+        classes.sort();
+        classes.dedup();
+        PitchClassSet { classes, edo }
+    }
+
+    /// The classes of the nearest steps of `edo` to each pitch.
+    pub fn from_pitches(pitches: &[Pitch], edo: i32) -> PitchClassSet {
+        let classes: Vec<i32> = pitches
+            .iter()
+            .map(|pitch| pitch_class(pitch, edo))
+            .collect();
+        PitchClassSet::new(&classes, edo)
+    }
+
+    pub fn from_chord(chord: &Chord, edo: i32) -> PitchClassSet {
+        PitchClassSet::from_pitches(&chord.0, edo)
+    }
+
+    /// The set as a chord rising from the C in `octave`, where octave 4
+    /// starts at middle C.
+    pub fn to_chord(&self, octave: i32) -> Chord {
+        let c = Pitch::from_midi(f64::from((octave + 1) * 12));
+        Chord(
+            self.classes
+                .iter()
+                .map(|class| c.offset_cents(f64::from(*class) * 1200.0 / f64::from(self.edo)))
+                .collect(),
+        )
+    }
+
+    pub fn classes(&self) -> &[i32] {
+        &self.classes
+    }
+
+    pub fn edo(&self) -> i32 {
+        self.edo
+    }
+
+    pub fn len(&self) -> usize {
+        self.classes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+
+    /// Tn, every class moved up `steps`.
+    pub fn transpose(&self, steps: i32) -> PitchClassSet {
+        let classes: Vec<i32> = self.classes.iter().map(|class| class + steps).collect();
+        PitchClassSet::new(&classes, self.edo)
+    }
+
+    /// I, every class reflected about C.
+    pub fn invert(&self) -> PitchClassSet {
+        let classes: Vec<i32> = self.classes.iter().map(|class| -class).collect();
+        PitchClassSet::new(&classes, self.edo)
+    }
+
+    /// TnI, inversion followed by transposition by `steps`.
+    pub fn transpose_invert(&self, steps: i32) -> PitchClassSet {
+        self.invert().transpose(steps)
+    }
+
+    pub fn complement(&self) -> PitchClassSet {
+        let classes: Vec<i32> = (0..self.edo)
+            .filter(|class| !self.classes.contains(class))
+            .collect();
+        PitchClassSet::new(&classes, self.edo)
+    }
+
+    /// The `n` for which `other` is Tn of this set, if any.
+    pub fn transposition_to(&self, other: &PitchClassSet) -> Option<i32> {
+        (0..self.edo).find(|steps| self.transpose(*steps) == *other)
+    }
+
+    /// The `n` for which `other` is TnI of this set, if any.
+    pub fn inversion_to(&self, other: &PitchClassSet) -> Option<i32> {
+        (0..self.edo).find(|steps| self.transpose_invert(*steps) == *other)
+    }
+
+    /// Whether the two sets are related by Tn or TnI, that is whether they
+    /// belong to the same set class.
+    pub fn is_equivalent(&self, other: &PitchClassSet) -> bool {
+        self.edo == other.edo && self.prime_form() == other.prime_form()
+    }
+
+    /// The ordering of the set that spans the smallest interval, breaking
+    /// ties as Rahn does by the interval from the first class to the
+    /// second last, then the third last and so on, then by the lowest
+    /// first class.
+    pub fn normal_form(&self) -> Vec<i32> {
+        let n = self.classes.len();
+        (0..n)
+            .map(|start| {
+                (0..n)
+                    .map(|i| self.classes[(start + i) % n])
+                    .collect::<Vec<i32>>()
+            })
+            .min_by(|a, b| {
+                packing(&self.above_first(a), &self.above_first(b)).then_with(|| a[0].cmp(&b[0]))
+            })
+            .unwrap_or_default()
+    }
+
+    /// The most compact form of the set or its inversion transposed to
+    /// start on 0, breaking ties as Rahn does by packing the last classes
+    /// most tightly rather than packing to the left as Forte does. This
+    /// differs from Forte's prime form for a handful of set classes, such as
+    /// 5-20.
+    pub fn prime_form(&self) -> Vec<i32> {
+        let inversion = self.invert();
+        [self, &inversion]
+            .iter()
+            .map(|set| set.above_first(&set.normal_form()))
+            .min_by(|a, b| packing(a, b))
+            .unwrap_or_default()
+    }
+
+    /// The number of each interval class between pairs of classes in the
+    /// set, from a single step up to half an octave.
+    pub fn interval_class_vector(&self) -> Vec<usize> {
+        let mut vector = vec![0; (self.edo / 2) as usize];
+        for (i, a) in self.classes.iter().enumerate() {
+            for b in &self.classes[i + 1..] {
+                let interval = (b - a).rem_euclid(self.edo);
+                let class = interval.min(self.edo - interval);
+                if class > 0 {
+                    vector[class as usize - 1] += 1;
+                }
+            }
+        }
+        vector
+    }
+
+    /// The set class's name in Forte's catalogue, such as "4-Z15", for
+    /// 12-EDO sets of two to ten classes.
+    pub fn forte_number(&self) -> Option<String> {
+        if self.edo != 12 {
+            return None;
+        }
+        match self.len() {
+            2 => Some(format!(
+                "2-{}",
+                self.interval_class_vector().iter().position(|n| *n > 0)? + 1
+            )),
+            3..=6 => {
+                let prime = self.prime_form();
+                FORTE
+                    .iter()
+                    .find(|(_, classes)| PitchClassSet::new(classes, 12).prime_form() == prime)
+                    .map(|(name, _)| name.to_string())
+            }
+            7..=10 => {
+                let name = self.complement().forte_number()?;
+                Some(format!("{}{}", self.len(), &name[name.find('-')?..]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the sets share an interval class vector without being in the
+    /// same set class.
+    pub fn is_z_related(&self, other: &PitchClassSet) -> bool {
+        self.edo == other.edo
+            && self.interval_class_vector() == other.interval_class_vector()
+            && !self.is_equivalent(other)
+    }
+
+    /// The prime form of a set Z-related to this one, if there is one. This
+    /// searches every set of the same size, so it gets slow for large
+    /// divisions of the octave.
+    pub fn z_correspondent(&self) -> Option<PitchClassSet> {
+        let mut found = None;
+        if !self.is_empty() {
+            let mut classes = vec![0];
+            subsets(self, &mut classes, &mut found);
+        }
+        found
+    }
+
+    // `ordered` as intervals above its first class.
+    fn above_first(&self, ordered: &[i32]) -> Vec<i32> {
+        ordered
+            .iter()
+            .map(|class| (class - ordered[0]).rem_euclid(self.edo))
+            .collect()
+    }
+}
+
+// Orders sets of intervals above a first class by their last interval, then
+// the one before and so on, smallest first.
+fn packing(a: &[i32], b: &[i32]) -> Ordering {
+    a.iter().rev().cmp(b.iter().rev())
+}
+
+// Extends `classes` to every set of the size of `set` that starts on 0,
+// stopping at the first that is Z-related to it.
+fn subsets(set: &PitchClassSet, classes: &mut Vec<i32>, found: &mut Option<PitchClassSet>) {
+    if found.is_some() {
+        return;
+    }
+    if classes.len() == set.len() {
+        let candidate = PitchClassSet::new(classes, set.edo);
+        if set.is_z_related(&candidate) {
+            *found = Some(PitchClassSet::new(&candidate.prime_form(), set.edo));
+        }
+        return;
+    }
+    for class in classes[classes.len() - 1] + 1..set.edo {
+        classes.push(class);
+        subsets(set, classes, found);
+        classes.pop();
+    }
+}
+
+fn pitch_class(pitch: &Pitch, edo: i32) -> i32 {
+    let c = Pitch::from_midi(0.0);
+    let steps = (c.cents_to(pitch) * f64::from(edo) / 1200.0).round() as i32;
+    steps.rem_euclid(edo)
+}
+
+/// A tone row: an ordering of every pitch class of an equal division of the
+/// octave, each used once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Row {
+    classes: Vec<i32>,
+    edo: i32,
+}
+
+impl Row {
+    /// Returns `None` unless `classes` uses each class of `edo` exactly once,
+    /// including when `edo` has no divisions.
+    pub fn new(classes: &[i32], edo: i32) -> Option<Row> {
+        if edo <= 0 {
+            return None;
+        }
+        let classes: Vec<i32> = classes.iter().map(|class| class.rem_euclid(edo)).collect();
+        let set = PitchClassSet::new(&classes, edo);
+        if classes.len() != edo as usize || set.len() != classes.len() {
+            return None;
+        }
+        Some(Row { classes, edo })
+    }
+
+    pub fn from_pitches(pitches: &[Pitch], edo: i32) -> Option<Row> {
+        if edo <= 0 {
+            return None;
+        }
+        let classes: Vec<i32> = pitches
+            .iter()
+            .map(|pitch| pitch_class(pitch, edo))
+            .collect();
+        Row::new(&classes, edo)
+    }
+
+    pub fn classes(&self) -> &[i32] {
+        &self.classes
+    }
+
+    /// P`n`, the row transposed to start on class `n`.
+    pub fn prime(&self, n: i32) -> Vec<i32> {
+        let steps = n - self.classes[0];
+        self.classes
+            .iter()
+            .map(|class| (class + steps).rem_euclid(self.edo))
+            .collect()
+    }
+
+    /// I`n`, the row inverted to start on class `n`.
+    pub fn inversion(&self, n: i32) -> Vec<i32> {
+        let first = self.classes[0];
+        self.classes
+            .iter()
+            .map(|class| (n + first - class).rem_euclid(self.edo))
+            .collect()
+    }
+
+    /// R`n`, P`n` backwards.
+    pub fn retrograde(&self, n: i32) -> Vec<i32> {
+        self.prime(n).into_iter().rev().collect()
+    }
+
+    /// RI`n`, I`n` backwards.
+    pub fn retrograde_inversion(&self, n: i32) -> Vec<i32> {
+        self.inversion(n).into_iter().rev().collect()
+    }
+
+    /// The row matrix: the row itself on top, its inversion down the left
+    /// and each line the prime form starting on the class to its left, so
+    /// retrogrades read right to left and inversions down the columns.
+    pub fn matrix(&self) -> Vec<Vec<i32>> {
+        self.inversion(self.classes[0])
+            .into_iter()
+            .map(|first| self.prime(first))
+            .collect()
+    }
+}
@@ -0,0 +1,36 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+/// The generator to hand to the crate's stochastic functions when a result
+/// must be reproducible. The same seed gives the same output for a given
+/// version of `rand`.
+pub fn rng_from_seed(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+/// A new seed from the thread's entropy source, for runs that should differ
+/// but still be repeatable later.
+pub fn fresh_seed() -> u64 {
+    thread_rng().gen()
+}
+
+/// A generated value together with the seed it was generated from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Seeded<T> {
+    pub seed: u64,
+    pub value: T,
+}
+
+impl<T> Seeded<T> {
+    pub fn generate<F: FnOnce(&mut StdRng) -> T>(seed: u64, generate: F) -> Seeded<T> {
+        Seeded {
+            seed,
+            value: generate(&mut rng_from_seed(seed)),
+        }
+    }
+
+    /// Generates again from the recorded seed.
+    pub fn regenerate<F: FnOnce(&mut StdRng) -> T>(&self, generate: F) -> Seeded<T> {
+        Seeded::generate(self.seed, generate)
+    }
+}
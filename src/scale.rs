@@ -0,0 +1,369 @@
+use super::tuning::{ratio_to_cents, Tuning};
+use super::Pitch;
+
+// How far a pitch may be from a scale tone and still count as on it.
+const TOLERANCE_CENTS: f64 = 1.0;
+
+const MAJOR: [f64; 7] = [200.0, 200.0, 100.0, 200.0, 200.0, 200.0, 100.0];
+const MAJOR_MODES: [&str; 7] = [
+    "ionian",
+    "dorian",
+    "phrygian",
+    "lydian",
+    "mixolydian",
+    "aeolian",
+    "locrian",
+];
+
+// One step of 13 equal divisions of the 3:1 tritave.
+const BOHLEN_PIERCE: f64 = 146.304_230_835_799;
+
+// Step sizes in cents. Maqamat use quarter tones, ragas their nearest 12-TET
+// thaat. The Bohlen-Pierce scales repeat at the tritave and Wendy Carlos'
+// alpha, beta and gamma scales don't repeat at all beyond their single step.
+const CATALOGUE: &[(&str, &[f64])] = &[
+    ("major", &MAJOR),
+    ("minor", &[200.0, 100.0, 200.0, 200.0, 100.0, 200.0, 200.0]),
+    (
+        "natural_minor",
+        &[200.0, 100.0, 200.0, 200.0, 100.0, 200.0, 200.0],
+    ),
+    (
+        "harmonic_minor",
+        &[200.0, 100.0, 200.0, 200.0, 100.0, 300.0, 100.0],
+    ),
+    (
+        "melodic_minor",
+        &[200.0, 100.0, 200.0, 200.0, 200.0, 200.0, 100.0],
+    ),
+    ("major_pentatonic", &[200.0, 200.0, 300.0, 200.0, 300.0]),
+    ("minor_pentatonic", &[300.0, 200.0, 200.0, 300.0, 200.0]),
+    ("blues", &[300.0, 200.0, 100.0, 100.0, 300.0, 200.0]),
+    ("whole_tone", &[200.0; 6]),
+    (
+        "octatonic_half_whole",
+        &[100.0, 200.0, 100.0, 200.0, 100.0, 200.0, 100.0, 200.0],
+    ),
+    (
+        "octatonic_whole_half",
+        &[200.0, 100.0, 200.0, 100.0, 200.0, 100.0, 200.0, 100.0],
+    ),
+    ("chromatic", &[100.0; 12]),
+    (
+        "maqam_rast",
+        &[200.0, 150.0, 150.0, 200.0, 200.0, 150.0, 150.0],
+    ),
+    (
+        "maqam_bayati",
+        &[150.0, 150.0, 200.0, 200.0, 100.0, 200.0, 200.0],
+    ),
+    (
+        "maqam_hijaz",
+        &[100.0, 300.0, 100.0, 200.0, 100.0, 200.0, 200.0],
+    ),
+    (
+        "maqam_saba",
+        &[150.0, 150.0, 100.0, 300.0, 100.0, 200.0, 200.0],
+    ),
+    (
+        "raga_bhairav",
+        &[100.0, 300.0, 100.0, 200.0, 100.0, 300.0, 100.0],
+    ),
+    (
+        "raga_yaman",
+        &[200.0, 200.0, 200.0, 100.0, 200.0, 200.0, 100.0],
+    ),
+    (
+        "raga_todi",
+        &[100.0, 200.0, 300.0, 100.0, 100.0, 300.0, 100.0],
+    ),
+    (
+        "raga_purvi",
+        &[100.0, 300.0, 200.0, 100.0, 100.0, 300.0, 100.0],
+    ),
+    ("raga_marwa", &[100.0, 300.0, 200.0, 300.0, 200.0, 100.0]),
+    (
+        "raga_kafi",
+        &[200.0, 100.0, 200.0, 200.0, 200.0, 100.0, 200.0],
+    ),
+    (
+        "raga_bhairavi",
+        &[100.0, 200.0, 200.0, 200.0, 100.0, 200.0, 200.0],
+    ),
+    ("bohlen_pierce", &[BOHLEN_PIERCE; 13]),
+    (
+        "bohlen_pierce_lambda",
+        &[
+            2.0 * BOHLEN_PIERCE,
+            BOHLEN_PIERCE,
+            BOHLEN_PIERCE,
+            2.0 * BOHLEN_PIERCE,
+            BOHLEN_PIERCE,
+            2.0 * BOHLEN_PIERCE,
+            BOHLEN_PIERCE,
+            2.0 * BOHLEN_PIERCE,
+            BOHLEN_PIERCE,
+        ],
+    ),
+    ("carlos_alpha", &[77.965]),
+    ("carlos_beta", &[63.833]),
+    ("carlos_gamma", &[35.099]),
+];
+
+#[derive(Clone, Debug)]
+pub struct Scale {
+    // Size of each step in cents. The steps sum to the period the scale
+    // repeats at, which needn't be an octave.
+    steps: Vec<f64>,
+}
+
+impl Scale {
+    /// A scale from the fractional increase in frequency of each step, so
+    /// `16.0 / 15.0 - 1.0` is a just semitone.
+    pub fn new(interval_pattern: &[f32]) -> Self {
+        Self {
+            steps: interval_pattern
+                .iter()
+                .map(|i| ratio_to_cents(1.0 + f64::from(*i)))
+                .collect(),
+        }
+    }
+
+    /// A scale from the size of each step in cents.
+    pub fn from_cents(steps: &[f64]) -> Self {
+        Self {
+            steps: steps.to_vec(),
+        }
+    }
+
+    /// A scale or mode from the catalogue by name, ignoring case and treating
+    /// spaces, hyphens and underscores alike, e.g. "dorian",
+    /// "harmonic minor" or "maqam-hijaz". See `Scale::names`.
+    pub fn named(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase().replace(&[' ', '-'][..], "_");
+        if let Some(mode) = MAJOR_MODES.iter().position(|mode| *mode == name) {
+            return Some(Scale::from_cents(&MAJOR).mode(mode));
+        }
+        CATALOGUE
+            .iter()
+            .find(|(scale, _)| *scale == name)
+            .map(|(_, steps)| Scale::from_cents(steps))
+    }
+
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        MAJOR_MODES
+            .iter()
+            .cloned()
+            .chain(CATALOGUE.iter().map(|(name, _)| *name))
+    }
+
+    /// The mode starting on degree `degree` of this scale, so mode 1 of the
+    /// major scale is dorian.
+    pub fn mode(&self, degree: usize) -> Self {
+        let mut steps = self.steps.clone();
+        if !steps.is_empty() {
+            let len = steps.len();
+            steps.rotate_left(degree % len);
+        }
+        Self { steps }
+    }
+
+    /// Number of degrees in one period.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Size of each step in cents.
+    pub fn steps(&self) -> &[f64] {
+        &self.steps
+    }
+
+    /// The interval the scale repeats at, in cents. 1200 for octave scales
+    /// and 1901.955 for the tritave of the Bohlen-Pierce scale.
+    pub fn period(&self) -> f64 {
+        self.steps.iter().sum()
+    }
+
+    /// Cents of `degree` above the tonic, counting down into lower periods
+    /// for negative degrees. Every degree of an empty scale is the tonic.
+    pub fn degree_cents(&self, degree: i32) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let len = self.len() as i32;
+        let within: f64 = self.steps[..degree.rem_euclid(len) as usize].iter().sum();
+        f64::from(degree.div_euclid(len)) * self.period() + within
+    }
+
+    /// The degree of the scale tone nearest to `pitch` when the scale starts
+    /// on `tonic`, zero for scales with no period.
+    pub fn nearest_degree(&self, tonic: &Pitch, pitch: &Pitch) -> i32 {
+        let period = self.period();
+        if period == 0.0 {
+            return 0;
+        }
+        let cents = tonic.cents_to(pitch);
+        let periods = (cents / period).floor();
+        let within = cents - periods * period;
+        let mut position = 0.0;
+        let mut nearest = (within.abs(), 0);
+        for (i, step) in self.steps.iter().enumerate() {
+            position += step;
+            let distance = (within - position).abs();
+            if distance < nearest.0 {
+                nearest = (distance, i as i32 + 1);
+            }
+        }
+        periods as i32 * self.len() as i32 + nearest.1
+    }
+
+    /// The scale tone nearest to `pitch`.
+    pub fn nearest(&self, tonic: &Pitch, pitch: &Pitch) -> Pitch {
+        let degree = self.nearest_degree(tonic, pitch);
+        tonic.offset_cents(self.degree_cents(degree))
+    }
+
+    /// The degree `pitch` falls on, if it is within a cent of a scale tone.
+    pub fn degree_of(&self, tonic: &Pitch, pitch: &Pitch) -> Option<i32> {
+        let degree = self.nearest_degree(tonic, pitch);
+        let error = tonic.cents_to(pitch) - self.degree_cents(degree);
+        if error.abs() <= TOLERANCE_CENTS {
+            Some(degree)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, tonic: &Pitch, pitch: &Pitch) -> bool {
+        self.degree_of(tonic, pitch).is_some()
+    }
+
+    /// A scale whose consecutive notes are `steps` degrees apart in `tuning`,
+    /// so `&[2, 2, 1, 2, 2, 2, 1]` in a twelve note tuning is the major scale.
+    pub fn from_tuning(tuning: &Tuning, steps: &[i32]) -> Self {
+        let mut degree = 0;
+        let steps = steps
+            .iter()
+            .map(|step| {
+                let cents = tuning.cents(degree + step) - tuning.cents(degree);
+                degree += step;
+                cents
+            })
+            .collect();
+        Self { steps }
+    }
+
+    /// The pitch `degree` steps above `base_pitch`, or below it for negative
+    /// degrees. Whole periods are stacked exactly, so for an octave scale
+    /// `pitch(base, -len)` is precisely an octave down.
+    pub fn pitch(&self, base_pitch: &Pitch, degree: i32) -> Pitch {
+        base_pitch.offset_cents(self.degree_cents(degree))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Pitch, b: f64) {
+        assert!(
+            (f64::from(a.0) - b).abs() < b * 1e-5,
+            "{} is not close to {}",
+            a.0,
+            b
+        );
+    }
+
+    #[test]
+    fn descending_is_the_inverse_of_ascending() {
+        let base = Pitch(220.0);
+        for name in Scale::names() {
+            let scale = Scale::named(name).unwrap();
+            let len = scale.len() as i32;
+            for degree in -30..30 {
+                let pitch = scale.pitch(&base, degree);
+                assert_eq!(scale.degree_of(&base, &pitch), Some(degree), "{}", name);
+                let below = scale.pitch(&base, degree - 1);
+                let step = scale.steps()[(degree - 1).rem_euclid(len) as usize];
+                assert!((below.cents_to(&pitch) - step).abs() < 0.01, "{}", name);
+            }
+            for periods in 1..4 {
+                let up = scale.pitch(&base, periods * len);
+                let down = scale.pitch(&base, -periods * len);
+                assert_close(down, 220.0 / (f64::from(up.0) / 220.0));
+            }
+        }
+    }
+
+    #[test]
+    fn whole_periods_are_exact() {
+        let semi = 16.0 / 15.0 - 1.0;
+        let whole = semi * 2.0;
+        let major = Scale::new(&[whole, whole, semi, whole, whole, whole, semi]);
+        let base = Pitch(220.0);
+        let period = 2.0f64.powf(major.period() / 1200.0);
+        assert_close(major.pitch(&base, -7), 220.0 / period);
+        assert_close(major.pitch(&base, 7), 220.0 * period);
+        assert_close(major.pitch(&base, -14), 220.0 / period / period);
+
+        let major = Scale::named("major").unwrap();
+        assert_close(major.pitch(&base, -7), 110.0);
+        assert_close(major.pitch(&base, -21), 27.5);
+        assert_close(major.pitch(&base, 14), 880.0);
+    }
+
+    #[test]
+    fn degrees_step_consistently_across_periods() {
+        let scale = Scale::named("harmonic_minor").unwrap();
+        let base = Pitch(261.63);
+        for degree in -20..20 {
+            let a = scale.pitch(&base, degree);
+            let b = scale.pitch(&base, degree + 1);
+            let step = scale.steps()[(degree.rem_euclid(7)) as usize];
+            assert!((a.cents_to(&b) - step).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn bohlen_pierce_repeats_at_the_tritave() {
+        let base = Pitch(200.0);
+        for name in &["bohlen_pierce", "bohlen_pierce_lambda"] {
+            let scale = Scale::named(name).unwrap();
+            let len = scale.len() as i32;
+            assert!((scale.period() - 1200.0 * 3.0f64.log2()).abs() < 1e-6);
+            assert_close(scale.pitch(&base, len), 600.0);
+            assert_close(scale.pitch(&base, -len), 200.0 / 3.0);
+            assert_close(scale.pitch(&base, 2 * len), 1800.0);
+        }
+    }
+
+    #[test]
+    fn carlos_scales_do_not_repeat_at_the_octave() {
+        let base = Pitch(440.0);
+        let alpha = Scale::named("carlos_alpha").unwrap();
+        // Nine alpha steps approximate a perfect fifth but not exactly
+        let fifth = alpha.pitch(&base, 9);
+        assert!((base.cents_to(&fifth) - 701.685).abs() < 1e-2);
+        assert_eq!(alpha.degree_of(&base, &Pitch(880.0)), None);
+        assert_eq!(alpha.nearest_degree(&base, &Pitch(880.0)), 15);
+
+        let beta = Scale::named("carlos_beta").unwrap();
+        assert_eq!(beta.nearest_degree(&base, &Pitch(220.0)), -19);
+        let down = beta.pitch(&base, -19);
+        assert_close(beta.pitch(&down, 19), 440.0);
+    }
+
+    #[test]
+    fn from_tuning_follows_the_tuning() {
+        let tuning = Tuning::just_intonation();
+        let scale = Scale::from_tuning(&tuning, &[2, 2, 1, 2, 2, 2, 1]);
+        let base = Pitch(240.0);
+        assert_close(scale.pitch(&base, 2), 300.0);
+        assert_close(scale.pitch(&base, -2), 240.0 * 5.0 / 3.0 / 2.0);
+        assert_close(scale.pitch(&base, -7), 120.0);
+    }
+}
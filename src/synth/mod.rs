@@ -1,21 +1,213 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::f64::consts::{FRAC_PI_4, LN_2};
+use std::iter::Sum;
+use std::mem;
+use std::ops::{Add, AddAssign, Mul};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 
+use super::tempo::{BeatNote, TempoMap};
 use super::Pitch;
 
+pub mod render;
 pub mod simple_instruments;
 
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Frame {
+    pub left: f64,
+    pub right: f64,
+}
+
+impl Frame {
+    pub fn new(left: f64, right: f64) -> Frame {
+        Frame { left, right }
+    }
+
+    pub fn mono(sample: f64) -> Frame {
+        Frame::new(sample, sample)
+    }
+
+    pub fn downmix(&self) -> f64 {
+        (self.left + self.right) * 0.5
+    }
+
+    /// Equal-power balance where -1.0 is hard left, 1.0 is hard right and 0.0
+    /// leaves the frame untouched.
+    pub fn pan(&self, pan: f64) -> Frame {
+        let (left, right) = pan_gains(pan);
+        Frame::new(
+            self.left * left * 2.0f64.sqrt(),
+            self.right * right * 2.0f64.sqrt(),
+        )
+    }
+}
+
+fn pan_gains(pan: f64) -> (f64, f64) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+impl Add for Frame {
+    type Output = Frame;
+    fn add(self, other: Frame) -> Frame {
+        Frame::new(self.left + other.left, self.right + other.right)
+    }
+}
+
+impl AddAssign for Frame {
+    fn add_assign(&mut self, other: Frame) {
+        *self = *self + other;
+    }
+}
+
+impl Mul<f64> for Frame {
+    type Output = Frame;
+    fn mul(self, gain: f64) -> Frame {
+        Frame::new(self.left * gain, self.right * gain)
+    }
+}
+
+impl Sum for Frame {
+    fn sum<I: Iterator<Item = Frame>>(iter: I) -> Frame {
+        iter.fold(Frame::default(), Add::add)
+    }
+}
+
 pub trait Voice {
     fn sample(&mut self, delta_time: f64) -> f64;
-    fn play_pitch(&mut self, pitch: &Pitch);
+    fn note_on(&mut self, note: &NoteOn);
     fn stop(&mut self);
+
+    /// Moves a sounding voice to a new note without restarting its envelope,
+    /// gliding to the new pitch over `glide_time` seconds. Voices that cannot
+    /// slur simply start the note again.
+    fn legato(&mut self, note: &NoteOn, _glide_time: f64) {
+        self.note_on(note);
+    }
+
+    fn sample_frame(&mut self, delta_time: f64) -> Frame {
+        Frame::mono(self.sample(delta_time))
+    }
+
+    /// Fills `out` with consecutive samples. Voices that can render a whole
+    /// buffer more cheaply than sample by sample should override this.
+    fn process(&mut self, delta_time: f64, out: &mut [f64]) {
+        for sample in out.iter_mut() {
+            *sample = self.sample(delta_time);
+        }
+    }
+
+    fn process_frames(&mut self, delta_time: f64, out: &mut [Frame]) {
+        for frame in out.iter_mut() {
+            *frame = self.sample_frame(delta_time);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VoiceAllocation {
+    /// Cycle through the voices regardless of whether they are still sounding.
+    RoundRobin,
+    /// Use a released voice if there is one, otherwise cut off the note that
+    /// started longest ago.
+    StealOldest,
+    /// Use a released voice if there is one, otherwise cut off the quietest.
+    StealQuietest,
+    /// Restart the voice already playing the same pitch, otherwise as
+    /// `StealOldest`.
+    Retrigger,
+    /// Play everything on the first voice, slurring into notes that start
+    /// while the previous one is still held and gliding over `portamento`
+    /// seconds.
+    Legato { portamento: f64 },
+}
+
+struct VoiceSlot {
+    voice: Box<dyn Voice>,
+    // Sample positions at which the current note started and is released.
+    onset: i64,
+    end: i64,
+    pitch: Option<Pitch>,
+    level: f64,
+}
+
+impl VoiceSlot {
+    fn track_level<T: Level>(&mut self, samples: &[T], seconds: f64) {
+        // Peak follower that halves every 10ms, enough to rank voices by loudness.
+        self.level *= (-seconds * LN_2 / 0.01).exp();
+        for sample in samples {
+            self.level = self.level.max(sample.level());
+        }
+    }
+}
+
+trait Level {
+    fn level(&self) -> f64;
+}
+
+impl Level for f64 {
+    fn level(&self) -> f64 {
+        self.abs()
+    }
+}
+
+impl Level for Frame {
+    fn level(&self) -> f64 {
+        self.left.abs().max(self.right.abs())
+    }
+}
+
+static NEXT_NOTE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Identifies a scheduled note so it can be cancelled before it starts.
+/// Ids are unique across all instruments.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NoteId(u64);
+
+struct ScheduledNote {
+    start: i64,
+    length: i64,
+    id: NoteId,
+    note: Note,
+}
+
+impl PartialEq for ScheduledNote {
+    fn eq(&self, other: &ScheduledNote) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for ScheduledNote {}
+
+impl PartialOrd for ScheduledNote {
+    fn partial_cmp(&self, other: &ScheduledNote) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScheduledNote {
+    // Reversed so the max-heap yields the earliest start, and notes sharing a
+    // start come out in the order they were scheduled.
+    fn cmp(&self, other: &ScheduledNote) -> Ordering {
+        other
+            .start
+            .cmp(&self.start)
+            .then_with(|| other.id.cmp(&self.id))
+    }
 }
 
 pub struct Instrument {
-    voices: Vec<(Box<dyn Voice>, f64)>,
-    sequence: Vec<Note>,
-    clock: f64,
+    voices: Vec<VoiceSlot>,
+    next_voice: usize,
+    sequence: BinaryHeap<ScheduledNote>,
+    // Index of the next sample to be rendered.
+    position: i64,
     pub amp: f64,
+    pub pan: f64,
+    pub allocation: VoiceAllocation,
     sample_rate: f64,
+    scratch: Vec<f64>,
+    frame_scratch: Vec<Frame>,
 }
 
 impl Instrument {
@@ -26,63 +218,287 @@ impl Instrument {
     ) -> Instrument {
         Instrument {
             voices: (0..voice_count)
-                .map(|_| (voice_constructor(), 100000.0))
+                .map(|_| VoiceSlot {
+                    voice: voice_constructor(),
+                    onset: 0,
+                    end: -1,
+                    pitch: None,
+                    level: 0.0,
+                })
                 .collect(),
-            sequence: Vec::new(),
-            clock: 0.0,
+            next_voice: 0,
+            sequence: BinaryHeap::new(),
+            position: 0,
             amp: 1.0,
+            pan: 0.0,
+            allocation: VoiceAllocation::RoundRobin,
             sample_rate,
+            scratch: Vec::new(),
+            frame_scratch: Vec::new(),
         }
     }
 
     pub fn sample(&mut self) -> f64 {
-        let delta_time = 1.0 / self.sample_rate;
-        self.clock += delta_time;
-        while self.sequence.len() > 0 && self.clock >= self.sequence[0].onset {
-            let note = self.sequence.remove(0);
-            self.voices[0].0.play_pitch(&note.pitch);
-            self.voices[0].1 = self.clock + note.duration;
-            self.voices.rotate_left(1);
-        }
-        for (voice, end_time) in &mut self.voices {
-            if *end_time < self.clock {
-                voice.stop();
+        let delta_time = self.advance();
+        self.voices
+            .iter_mut()
+            .map(|slot| {
+                let sample = slot.voice.sample(delta_time);
+                slot.track_level(&[sample], delta_time);
+                sample
+            })
+            .sum::<f64>()
+            * self.amp
+    }
+
+    /// Like `sample` but keeps the stereo image of the voices. `pan` is not
+    /// applied here, it is left to `Instrumentation`.
+    pub fn sample_frame(&mut self) -> Frame {
+        let delta_time = self.advance();
+        self.voices
+            .iter_mut()
+            .map(|slot| {
+                let frame = slot.voice.sample_frame(delta_time);
+                slot.track_level(&[frame], delta_time);
+                frame
+            })
+            .sum::<Frame>()
+            * self.amp
+    }
+
+    /// Fills `out` with the same samples successive calls to `sample` would
+    /// produce, handing the voices whole runs between note events.
+    pub fn process(&mut self, out: &mut [f64]) {
+        let mut scratch = mem::take(&mut self.scratch);
+        self.process_runs(out, &mut scratch, |voice, delta_time, out| {
+            voice.process(delta_time, out)
+        });
+        self.scratch = scratch;
+    }
+
+    pub fn process_frames(&mut self, out: &mut [Frame]) {
+        let mut scratch = mem::take(&mut self.frame_scratch);
+        self.process_runs(out, &mut scratch, |voice, delta_time, out| {
+            voice.process_frames(delta_time, out)
+        });
+        self.frame_scratch = scratch;
+    }
+
+    fn process_runs<T, F>(&mut self, out: &mut [T], scratch: &mut Vec<T>, process: F)
+    where
+        T: Copy + Default + AddAssign + Mul<f64, Output = T> + Level,
+        F: Fn(&mut dyn Voice, f64, &mut [T]),
+    {
+        let mut start = 0;
+        while start < out.len() {
+            let delta_time = self.advance();
+            let len = 1 + self.skip_event_free(out.len() - start - 1);
+            let run = &mut out[start..start + len];
+            scratch.resize(len, T::default());
+            for sample in run.iter_mut() {
+                *sample = T::default();
+            }
+            for slot in &mut self.voices {
+                process(slot.voice.as_mut(), delta_time, scratch);
+                slot.track_level(scratch, delta_time * len as f64);
+                for (sample, voice_sample) in run.iter_mut().zip(scratch.iter()) {
+                    *sample += *voice_sample;
+                }
+            }
+            for sample in run.iter_mut() {
+                *sample = *sample * self.amp;
             }
+            start += len;
         }
-        self.voices.iter_mut().map(|v| v.0.sample(delta_time)).sum::<f64>() * self.amp
     }
 
-    pub fn schedule_note(&mut self, note: &Note) {
-        if note.onset >= self.clock {
-            let mut position = self.sequence.len();
-            for (i, other) in self.sequence.iter().enumerate() {
-                if note.onset < other.onset {
-                    position = i;
-                    break;
+    /// Moves forward over at most `limit` samples during which no note starts
+    /// or is released, returning how many were skipped.
+    fn skip_event_free(&mut self, limit: usize) -> usize {
+        let next_start = self.sequence.peek().map_or(i64::MAX, |next| next.start);
+        let next_release = self
+            .voices
+            .iter()
+            .map(|slot| slot.end)
+            .filter(|end| *end >= self.position)
+            .min()
+            .unwrap_or(i64::MAX);
+        let skipped = (next_start.min(next_release) - self.position).min(limit as i64);
+        self.position += skipped;
+        skipped as usize
+    }
+
+    /// Starts and releases the notes due at the current sample, then moves on
+    /// to the next one.
+    fn advance(&mut self) -> f64 {
+        let position = self.position;
+        while self
+            .sequence
+            .peek()
+            .is_some_and(|next| next.start <= position)
+        {
+            let scheduled = self.sequence.pop().unwrap();
+            if self.voices.is_empty() {
+                continue;
+            }
+            let event = NoteOn::from(&scheduled.note);
+            let index = self.allocate(&scheduled.note);
+            let slot = &mut self.voices[index];
+            match self.allocation {
+                // A note released on the sample this one starts still counts
+                // as held, so back to back notes slur.
+                VoiceAllocation::Legato { portamento }
+                    if slot.pitch.is_some() && slot.end >= position =>
+                {
+                    slot.voice.legato(&event, portamento);
                 }
+                _ => {
+                    slot.voice.note_on(&event);
+                    slot.onset = position;
+                }
+            }
+            slot.end = position + scheduled.length;
+            slot.pitch = Some(scheduled.note.pitch);
+        }
+        for slot in &mut self.voices {
+            if slot.end <= position {
+                slot.voice.stop();
+            }
+        }
+        self.position += 1;
+        1.0 / self.sample_rate
+    }
+
+    fn allocate(&mut self, note: &Note) -> usize {
+        let all = 0..self.voices.len();
+        let oldest = || {
+            self.min_voice_by(all.clone(), |slot| slot.onset as f64)
+                .unwrap()
+        };
+        match self.allocation {
+            VoiceAllocation::RoundRobin => {
+                let index = self.next_voice % self.voices.len();
+                self.next_voice = index + 1;
+                index
             }
-            self.sequence.insert(position, note.clone());
+            VoiceAllocation::StealOldest => self.released_voice().unwrap_or_else(oldest),
+            VoiceAllocation::StealQuietest => self
+                .released_voice()
+                .or_else(|| self.min_voice_by(all.clone(), |slot| slot.level))
+                .unwrap(),
+            VoiceAllocation::Retrigger => self
+                .voices
+                .iter()
+                .position(|slot| slot.pitch == Some(note.pitch))
+                .or_else(|| self.released_voice())
+                .unwrap_or_else(oldest),
+            VoiceAllocation::Legato { .. } => 0,
         }
     }
 
+    /// The quietest voice whose note has already been released.
+    fn released_voice(&self) -> Option<usize> {
+        let released = (0..self.voices.len()).filter(|i| self.voices[*i].end < self.position);
+        self.min_voice_by(released, |slot| slot.level)
+    }
+
+    fn min_voice_by<F: Fn(&VoiceSlot) -> f64>(
+        &self,
+        candidates: impl Iterator<Item = usize>,
+        key: F,
+    ) -> Option<usize> {
+        candidates.min_by(|a, b| {
+            key(&self.voices[*a])
+                .partial_cmp(&key(&self.voices[*b]))
+                .unwrap()
+        })
+    }
+
+    /// Queues `note` to start on the sample nearest its onset. Notes whose
+    /// onset has already passed are dropped and get no id.
+    pub fn schedule_note(&mut self, note: &Note) -> Option<NoteId> {
+        let scheduled = self.prepare(note)?;
+        let id = scheduled.id;
+        self.sequence.push(scheduled);
+        Some(id)
+    }
+
+    /// Schedules many notes at once, which is cheaper than scheduling them one
+    /// at a time. The returned ids line up with `notes`.
+    pub fn schedule_notes<'a, I: IntoIterator<Item = &'a Note>>(
+        &mut self,
+        notes: I,
+    ) -> Vec<Option<NoteId>> {
+        let mut ids = vec![];
+        let mut scheduled = vec![];
+        for note in notes {
+            let prepared = self.prepare(note);
+            ids.push(prepared.as_ref().map(|prepared| prepared.id));
+            scheduled.extend(prepared);
+        }
+        self.sequence.extend(scheduled);
+        ids
+    }
+
+    fn prepare(&self, note: &Note) -> Option<ScheduledNote> {
+        let start = (note.onset * self.sample_rate).round() as i64;
+        if start < self.position {
+            return None;
+        }
+        Some(ScheduledNote {
+            start,
+            length: (note.duration * self.sample_rate).round().max(0.0) as i64,
+            id: NoteId(NEXT_NOTE_ID.fetch_add(1, AtomicOrdering::Relaxed)),
+            note: note.clone(),
+        })
+    }
+
+    /// Removes a note that has not started yet. Returns false if it is unknown
+    /// to this instrument or already playing.
+    pub fn cancel_note(&mut self, id: NoteId) -> bool {
+        let before = self.sequence.len();
+        self.sequence.retain(|scheduled| scheduled.id != id);
+        self.sequence.len() != before
+    }
+
     pub fn exhausted(&self) -> bool {
-        self.sequence.len() == 0
+        self.sequence.is_empty()
+    }
+
+    /// Seconds until every scheduled and currently held note has been released.
+    pub fn time_remaining(&self) -> f64 {
+        let scheduled = self
+            .sequence
+            .iter()
+            .map(|scheduled| scheduled.start + scheduled.length)
+            .max()
+            .unwrap_or(0);
+        let held = self.voices.iter().map(|slot| slot.end).max().unwrap_or(0);
+        (scheduled.max(held) - self.position).max(0) as f64 / self.sample_rate
     }
 
     pub fn reset(&mut self) {
-        self.clock = 0.0;
+        for slot in &mut self.voices {
+            slot.onset -= self.position;
+            slot.end -= self.position;
+        }
+        self.position = 0;
         self.sequence.clear();
     }
 }
 
 pub struct Instrumentation {
     pub instruments: HashMap<usize, Instrument>,
+    scratch: Vec<f64>,
+    frame_scratch: Vec<Frame>,
 }
 
 impl Instrumentation {
     pub fn new() -> Instrumentation {
         Instrumentation {
             instruments: HashMap::new(),
+            scratch: Vec::new(),
+            frame_scratch: Vec::new(),
         }
     }
 
@@ -90,6 +506,14 @@ impl Instrumentation {
         self.instruments.insert(instrument_idx, instrument);
     }
 
+    pub fn set_pan(&mut self, instrument_idx: usize, pan: f64) {
+        self.instruments.get_mut(&instrument_idx).unwrap().pan = pan;
+    }
+
+    pub fn set_gain(&mut self, instrument_idx: usize, gain: f64) {
+        self.instruments.get_mut(&instrument_idx).unwrap().amp = gain;
+    }
+
     pub fn sample(&mut self) -> f64 {
         self.instruments
             .values_mut()
@@ -98,11 +522,114 @@ impl Instrumentation {
             * 0.1
     }
 
-    pub fn schedule_note(&mut self, note: &Note) {
+    pub fn sample_frame(&mut self) -> Frame {
+        self.instruments
+            .values_mut()
+            .map(|instrument| {
+                let pan = instrument.pan;
+                instrument.sample_frame().pan(pan)
+            })
+            .sum::<Frame>()
+            * 0.1
+    }
+
+    pub fn process(&mut self, out: &mut [f64]) {
+        for sample in out.iter_mut() {
+            *sample = 0.0;
+        }
+        self.scratch.resize(out.len(), 0.0);
+        for instrument in self.instruments.values_mut() {
+            instrument.process(&mut self.scratch);
+            for (sample, instrument_sample) in out.iter_mut().zip(self.scratch.iter()) {
+                *sample += instrument_sample * 0.1;
+            }
+        }
+    }
+
+    pub fn process_frames(&mut self, out: &mut [Frame]) {
+        for frame in out.iter_mut() {
+            *frame = Frame::default();
+        }
+        self.frame_scratch.resize(out.len(), Frame::default());
+        for instrument in self.instruments.values_mut() {
+            let pan = instrument.pan;
+            instrument.process_frames(&mut self.frame_scratch);
+            for (frame, instrument_frame) in out.iter_mut().zip(self.frame_scratch.iter()) {
+                *frame += instrument_frame.pan(pan) * 0.1;
+            }
+        }
+    }
+
+    /// Mixes into `out.len()` channels. Each instrument is downmixed to mono and
+    /// placed by its pan between adjacent channels, so -1.0 is the first
+    /// channel and 1.0 the last.
+    pub fn sample_channels(&mut self, out: &mut [f64]) {
+        for channel in out.iter_mut() {
+            *channel = 0.0;
+        }
+        if out.is_empty() {
+            return;
+        }
+        let last = out.len() - 1;
+        for instrument in self.instruments.values_mut() {
+            let pan = instrument.pan;
+            let sample = instrument.sample_frame().downmix() * 0.1;
+            if last == 0 {
+                out[0] += sample;
+                continue;
+            }
+            let position = (pan.clamp(-1.0, 1.0) + 1.0) * 0.5 * last as f64;
+            let channel = (position.floor() as usize).min(last - 1);
+            let (near, far) = pan_gains((position - channel as f64) * 2.0 - 1.0);
+            out[channel] += sample * near;
+            out[channel + 1] += sample * far;
+        }
+    }
+
+    pub fn schedule_note(&mut self, note: &Note) -> Option<NoteId> {
         self.instruments
             .get_mut(&note.instrument)
             .unwrap()
-            .schedule_note(note);
+            .schedule_note(note)
+    }
+
+    /// Bulk version of `schedule_note`, the returned ids line up with `notes`.
+    pub fn schedule_notes(&mut self, notes: &[Note]) -> Vec<Option<NoteId>> {
+        let mut ids = vec![None; notes.len()];
+        let mut by_instrument: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (i, note) in notes.iter().enumerate() {
+            by_instrument.entry(note.instrument).or_default().push(i);
+        }
+        for (instrument, indices) in by_instrument {
+            let scheduled = self
+                .instruments
+                .get_mut(&instrument)
+                .unwrap()
+                .schedule_notes(indices.iter().map(|i| &notes[*i]));
+            for (i, id) in indices.into_iter().zip(scheduled) {
+                ids[i] = id;
+            }
+        }
+        ids
+    }
+
+    pub fn schedule_beat_note(&mut self, note: &BeatNote, tempo: &TempoMap) -> Option<NoteId> {
+        self.schedule_note(&note.to_note(tempo))
+    }
+
+    pub fn schedule_beat_notes(
+        &mut self,
+        notes: &[BeatNote],
+        tempo: &TempoMap,
+    ) -> Vec<Option<NoteId>> {
+        let notes: Vec<Note> = notes.iter().map(|note| note.to_note(tempo)).collect();
+        self.schedule_notes(&notes)
+    }
+
+    pub fn cancel_note(&mut self, id: NoteId) -> bool {
+        self.instruments
+            .values_mut()
+            .any(|instrument| instrument.cancel_note(id))
     }
 
     pub fn exhausted(&self) -> bool {
@@ -111,6 +638,13 @@ impl Instrumentation {
             .all(|instrument| instrument.exhausted())
     }
 
+    pub fn time_remaining(&self) -> f64 {
+        self.instruments
+            .values()
+            .map(|instrument| instrument.time_remaining())
+            .fold(0.0, f64::max)
+    }
+
     pub fn reset(&mut self) {
         self.instruments
             .values_mut()
@@ -125,4 +659,23 @@ pub struct Note {
     pub onset: f64,
     pub duration: f64,
     pub amplitude: f64,
+    /// Voice specific `(index, value)` overrides applied when the note starts.
+    pub parameters: Vec<(usize, f64)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NoteOn {
+    pub pitch: Pitch,
+    pub velocity: f64,
+    pub parameters: Vec<(usize, f64)>,
+}
+
+impl<'a> From<&'a Note> for NoteOn {
+    fn from(note: &'a Note) -> NoteOn {
+        NoteOn {
+            pitch: note.pitch,
+            velocity: note.amplitude,
+            parameters: note.parameters.clone(),
+        }
+    }
 }
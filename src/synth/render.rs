@@ -0,0 +1,171 @@
+use std::io::{Seek, Write};
+use std::path::Path;
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use super::{Frame, Instrumentation};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BitDepth {
+    Int16,
+    Int24,
+    Float32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RenderSettings {
+    pub sample_rate: f64,
+    pub channels: u16,
+    pub bit_depth: BitDepth,
+    /// Seconds of audio rendered after the last note is released.
+    pub tail: f64,
+}
+
+impl RenderSettings {
+    pub fn new(sample_rate: f64) -> RenderSettings {
+        RenderSettings {
+            sample_rate,
+            channels: 1,
+            bit_depth: BitDepth::Int16,
+            tail: 1.0,
+        }
+    }
+
+    fn spec(&self) -> WavSpec {
+        let (bits_per_sample, sample_format) = match self.bit_depth {
+            BitDepth::Int16 => (16, SampleFormat::Int),
+            BitDepth::Int24 => (24, SampleFormat::Int),
+            BitDepth::Float32 => (32, SampleFormat::Float),
+        };
+        WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate as u32,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
+
+const BLOCK_FRAMES: usize = 1024;
+
+/// Pulls blocks of interleaved `settings.channels` samples from
+/// `instrumentation` until it is exhausted and every note has been released,
+/// then for a further `settings.tail` seconds.
+///
+/// Panics if `settings.channels` is zero.
+pub fn render_with<F: FnMut(&[f64])>(
+    instrumentation: &mut Instrumentation,
+    settings: &RenderSettings,
+    mut sink: F,
+) {
+    assert!(
+        settings.channels > 0,
+        "rendering needs at least one channel"
+    );
+    let mut block = Block::new(usize::from(settings.channels));
+    while !instrumentation.exhausted() {
+        sink(block.pull(instrumentation, BLOCK_FRAMES));
+    }
+    let remaining = instrumentation.time_remaining() + settings.tail;
+    let mut remaining = (remaining * settings.sample_rate).ceil() as usize;
+    while remaining > 0 {
+        let frames = remaining.min(BLOCK_FRAMES);
+        sink(block.pull(instrumentation, frames));
+        remaining -= frames;
+    }
+}
+
+/// Renders into a buffer of interleaved samples.
+///
+/// Panics if `settings.channels` is zero.
+pub fn render(instrumentation: &mut Instrumentation, settings: &RenderSettings) -> Vec<f64> {
+    let mut samples = Vec::new();
+    render_with(instrumentation, settings, |block| {
+        samples.extend_from_slice(block)
+    });
+    samples
+}
+
+struct Block {
+    channels: usize,
+    samples: Vec<f64>,
+    frames: Vec<Frame>,
+}
+
+impl Block {
+    fn new(channels: usize) -> Block {
+        Block {
+            channels,
+            samples: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    fn pull(&mut self, instrumentation: &mut Instrumentation, frames: usize) -> &[f64] {
+        self.samples.resize(frames * self.channels, 0.0);
+        match self.channels {
+            1 => instrumentation.process(&mut self.samples),
+            2 => {
+                self.frames.resize(frames, Frame::default());
+                instrumentation.process_frames(&mut self.frames);
+                for (samples, frame) in self.samples.chunks_mut(2).zip(self.frames.iter()) {
+                    samples[0] = frame.left;
+                    samples[1] = frame.right;
+                }
+            }
+            _ => {
+                for samples in self.samples.chunks_mut(self.channels) {
+                    instrumentation.sample_channels(samples);
+                }
+            }
+        }
+        &self.samples
+    }
+}
+
+/// Fails with `hound::Error::Unsupported` if `settings.channels` is zero.
+pub fn write_wav<W: Write + Seek>(
+    instrumentation: &mut Instrumentation,
+    writer: W,
+    settings: &RenderSettings,
+) -> hound::Result<()> {
+    if settings.channels == 0 {
+        return Err(hound::Error::Unsupported);
+    }
+    let mut writer = WavWriter::new(writer, settings.spec())?;
+    let mut result = Ok(());
+    render_with(instrumentation, settings, |block| {
+        for sample in block {
+            if result.is_ok() {
+                result = write_sample(&mut writer, settings.bit_depth, *sample);
+            }
+        }
+    });
+    result?;
+    writer.finalize()
+}
+
+pub fn render_wav<P: AsRef<Path>>(
+    instrumentation: &mut Instrumentation,
+    path: P,
+    settings: &RenderSettings,
+) -> hound::Result<()> {
+    if settings.channels == 0 {
+        return Err(hound::Error::Unsupported);
+    }
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write_wav(instrumentation, file, settings)
+}
+
+fn write_sample<W: Write + Seek>(
+    writer: &mut WavWriter<W>,
+    bit_depth: BitDepth,
+    sample: f64,
+) -> hound::Result<()> {
+    let clipped = sample.clamp(-1.0, 1.0);
+    match bit_depth {
+        BitDepth::Int16 => writer.write_sample((clipped * f64::from(i16::MAX)) as i16),
+        BitDepth::Int24 => writer.write_sample((clipped * 8_388_607.0) as i32),
+        BitDepth::Float32 => writer.write_sample(sample as f32),
+    }
+}
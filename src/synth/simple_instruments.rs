@@ -1,11 +1,14 @@
 use std::f64::consts::PI;
 use std::f64::MAX;
 
-use super::Voice;
+use rand::Rng;
+
+use super::{Frame, NoteOn, Voice};
 use crate::Pitch;
 
 pub struct Kick {
     amp: f64,
+    velocity: f64,
     since_event: f64,
     since_onset: f64,
     sounding: bool,
@@ -15,6 +18,7 @@ impl Kick {
     pub fn new(amplitude: f64) -> Kick {
         Kick {
             amp: amplitude,
+            velocity: 1.0,
             since_event: MAX,
             since_onset: MAX,
             sounding: false,
@@ -27,17 +31,18 @@ impl Voice for Kick {
         self.since_event += delta_time;
         self.since_onset += delta_time;
         let amp = if self.sounding {
-            self.amp * ads(0.005, 0.005, 0.75, self.since_event)
+            self.amp * self.velocity * ads(0.005, 0.005, 0.75, self.since_event)
         } else {
-            self.amp * sr(0.75, 0.01, self.since_event)
+            self.amp * self.velocity * sr(0.75, 0.01, self.since_event)
         };
         (90.0 * self.since_onset * 2.0 * PI).sin() * amp
     }
 
-    fn play_pitch(&mut self, _: &Pitch) {
+    fn note_on(&mut self, note: &NoteOn) {
         self.since_event = 0.0;
         self.since_onset = 0.0;
         self.sounding = true;
+        self.velocity = note.velocity;
     }
 
     fn stop(&mut self) {
@@ -48,58 +53,174 @@ impl Voice for Kick {
     }
 }
 
+const BELL_PARTIALS: [f64; 10] = [1.0, 2.23, 3.73, 4.81, 5.43, 6.24, 7.35, 8.12, 9.44, 10.21];
+
 pub struct AdditiveBell {
     pitch: Pitch,
     amp: f64,
+    velocity: f64,
+    spread: f64,
     since_event: f64,
-    since_onset: f64,
+    // Cycles of the fundamental since the note started, accumulated rather
+    // than derived from time so the pitch can change mid-note.
+    phase: f64,
+    glide_from: f64,
+    glide_time: f64,
+    since_glide: f64,
     sounding: bool,
 }
 
 impl AdditiveBell {
     pub fn new(amplitude: f64) -> AdditiveBell {
+        AdditiveBell::with_spread(amplitude, 0.0)
+    }
+
+    /// `spread` between 0.0 and 1.0 fans the partials out alternately left and
+    /// right when sampled in stereo, with the highest partial panned furthest.
+    pub fn with_spread(amplitude: f64, spread: f64) -> AdditiveBell {
         AdditiveBell {
             pitch: Pitch(440.0),
             amp: amplitude,
+            velocity: 1.0,
+            spread,
             since_event: MAX,
-            since_onset: MAX,
+            phase: 0.0,
+            glide_from: 440.0,
+            glide_time: 0.0,
+            since_glide: 0.0,
             sounding: false,
         }
     }
+
+    fn frequency(&self) -> f64 {
+        let target = self.pitch.0 as f64;
+        if self.since_glide >= self.glide_time {
+            target
+        } else {
+            self.glide_from * (target / self.glide_from).powf(self.since_glide / self.glide_time)
+        }
+    }
+
+    fn envelope(&mut self, delta_time: f64) -> f64 {
+        self.since_event += delta_time;
+        self.since_glide += delta_time;
+        self.phase += self.frequency() * delta_time;
+        if self.sounding {
+            self.amp * self.velocity * ads(0.01, 0.01, 0.7, self.since_event)
+        } else {
+            self.amp * self.velocity * sr(0.7, 0.3, self.since_event)
+        }
+    }
+
+    fn partial(&self, i: usize, amp: f64) -> f64 {
+        (BELL_PARTIALS[i] * self.phase * 2.0 * PI).sin() * amp * self.partial_weight(i)
+    }
+
+    // Softer notes lose their upper partials faster, so dynamics change the
+    // timbre as well as the level.
+    fn partial_weight(&self, i: usize) -> f64 {
+        (1.0 / 2.0f64.powi(i as i32 + 1)) * self.velocity.powf(i as f64 * 0.5)
+    }
+
+    fn silent(&self) -> bool {
+        !self.sounding && self.since_event > 0.3
+    }
+
+    fn skip(&mut self, delta_time: f64, samples: usize) {
+        self.since_event += delta_time * samples as f64;
+        self.since_glide += delta_time * samples as f64;
+    }
+
+    fn partial_pan(&self, i: usize) -> f64 {
+        let side = if i.is_multiple_of(2) { 1.0 } else { -1.0 };
+        self.spread * side * i as f64 / (BELL_PARTIALS.len() - 1) as f64
+    }
 }
 
 impl Voice for AdditiveBell {
     fn sample(&mut self, delta_time: f64) -> f64 {
-        let mut sample = 0.0;
-        self.since_event += delta_time;
-        self.since_onset += delta_time;
-        let amp = if self.sounding {
-            self.amp * ads(0.01, 0.01, 0.7, self.since_event)
+        let amp = self.envelope(delta_time);
+        if amp > 0.0 {
+            (0..BELL_PARTIALS.len()).map(|i| self.partial(i, amp)).sum()
         } else {
-            self.amp * sr(0.7, 0.3, self.since_event)
-        };
+            0.0
+        }
+    }
 
+    fn sample_frame(&mut self, delta_time: f64) -> Frame {
+        let amp = self.envelope(delta_time);
         if amp > 0.0 {
-            [
-                1.0f64, 2.23, 3.73, 4.81, 5.43, 6.24, 7.35, 8.12, 9.44, 10.21,
-            ]
+            (0..BELL_PARTIALS.len())
+                .map(|i| Frame::mono(self.partial(i, amp)).pan(self.partial_pan(i)))
+                .sum()
+        } else {
+            Frame::default()
+        }
+    }
+
+    fn process(&mut self, delta_time: f64, out: &mut [f64]) {
+        if self.silent() {
+            self.skip(delta_time, out.len());
+            for sample in out.iter_mut() {
+                *sample = 0.0;
+            }
+            return;
+        }
+        let partials: Vec<(f64, f64)> = BELL_PARTIALS
             .iter()
             .enumerate()
-            .for_each(|(i, m)| {
-                let i = i + 1;
-                sample += (self.pitch.0 as f64 * m * self.since_onset * 2.0 * PI).sin()
-                    * amp
-                    * (1.0 / 2.0f64.powf(i as f64));
-            })
+            .map(|(i, m)| (m * 2.0 * PI, self.partial_weight(i)))
+            .collect();
+        for sample in out.iter_mut() {
+            let amp = self.envelope(delta_time);
+            *sample = 0.0;
+            for (ratio, weight) in &partials {
+                *sample += (ratio * self.phase).sin() * amp * weight;
+            }
+        }
+    }
+
+    fn process_frames(&mut self, delta_time: f64, out: &mut [Frame]) {
+        if self.silent() {
+            self.skip(delta_time, out.len());
+            for frame in out.iter_mut() {
+                *frame = Frame::default();
+            }
+            return;
+        }
+        let pans: Vec<Frame> = (0..BELL_PARTIALS.len())
+            .map(|i| Frame::mono(1.0).pan(self.partial_pan(i)))
+            .collect();
+        for frame in out.iter_mut() {
+            let amp = self.envelope(delta_time);
+            *frame = Frame::default();
+            if amp > 0.0 {
+                for (i, pan) in pans.iter().enumerate() {
+                    *frame += *pan * self.partial(i, amp);
+                }
+            }
         }
-        sample
     }
 
-    fn play_pitch(&mut self, pitch: &Pitch) {
+    fn note_on(&mut self, note: &NoteOn) {
         self.since_event = 0.0;
-        self.since_onset = 0.0;
+        self.phase = 0.0;
+        self.glide_time = 0.0;
         self.sounding = true;
-        self.pitch = *pitch;
+        self.pitch = note.pitch;
+        self.velocity = note.velocity;
+    }
+
+    fn legato(&mut self, note: &NoteOn, glide_time: f64) {
+        if !self.sounding {
+            self.note_on(note);
+            return;
+        }
+        self.glide_from = self.frequency();
+        self.glide_time = glide_time;
+        self.since_glide = 0.0;
+        self.pitch = note.pitch;
+        self.velocity = note.velocity;
     }
 
     fn stop(&mut self) {
@@ -142,15 +263,126 @@ pub enum Function {
     ADSR(usize, usize, usize, usize, usize)
 }
 
+impl Function {
+    fn output(&self) -> usize {
+        match *self {
+            Function::Sin(_, _, output)
+            | Function::Copy(_, output)
+            | Function::Multiply(_, output)
+            | Function::Scale(_, output)
+            | Function::Add(_, output)
+            | Function::ADSR(_, _, _, _, output) => output,
+        }
+    }
+
+    // Whether the output is updated rather than overwritten.
+    fn in_place(&self) -> bool {
+        !matches!(*self, Function::Sin(..) | Function::Copy(..))
+    }
+
+    // The slots read, besides the output of an update in place.
+    fn inputs(&self) -> Vec<usize> {
+        match *self {
+            Function::Sin(freq, phase, _) => vec![freq, phase],
+            Function::Copy(input, _)
+            | Function::Multiply(input, _)
+            | Function::Scale(input, _)
+            | Function::Add(input, _) => vec![input],
+            Function::ADSR(a, d, s, r, _) => vec![a, d, s, r],
+        }
+    }
+
+    // The new value of the output, reading other slots through `slot`.
+    fn apply<S: Fn(usize) -> f64>(
+        &self,
+        slot: S,
+        since_onset: f64,
+        since_event: f64,
+        sounding: bool,
+    ) -> f64 {
+        match *self {
+            Function::Sin(freq, phase, _) => {
+                (slot(freq) * (since_onset + slot(phase)) * 2.0 * PI).sin()
+            }
+            Function::Copy(input, _) => slot(input),
+            Function::Multiply(input, output) => slot(output) * slot(input),
+            Function::Scale(input, output) => slot(output) * (1.0 + slot(input)),
+            Function::Add(input, output) => slot(output) + slot(input),
+            Function::ADSR(a, d, s, r, output) => {
+                let amp = if sounding {
+                    ads(slot(a), slot(d), slot(s), since_event)
+                } else {
+                    sr(slot(s), slot(r), since_event)
+                };
+                slot(output) * amp
+            }
+        }
+    }
+
+    // Like `apply` over a block of `output.len()` samples, the output slot's
+    // buffer having been taken out of `buffers`. The output mustn't also be
+    // one of `inputs`.
+    fn apply_block(
+        &self,
+        output: &mut [f64],
+        buffers: &[Vec<f64>],
+        delta_time: f64,
+        mut since_onset: f64,
+        mut since_event: f64,
+        sounding: bool,
+    ) {
+        match *self {
+            Function::Sin(freq, phase, _) => {
+                let inputs = output.iter_mut().zip(&buffers[freq]).zip(&buffers[phase]);
+                for ((value, freq), phase) in inputs {
+                    since_onset += delta_time;
+                    *value = (freq * (since_onset + phase) * 2.0 * PI).sin();
+                }
+            }
+            Function::Copy(input, _) => output.copy_from_slice(&buffers[input]),
+            Function::Multiply(input, _) => {
+                for (value, input) in output.iter_mut().zip(&buffers[input]) {
+                    *value *= input;
+                }
+            }
+            Function::Scale(input, _) => {
+                for (value, input) in output.iter_mut().zip(&buffers[input]) {
+                    *value *= 1.0 + input;
+                }
+            }
+            Function::Add(input, _) => {
+                for (value, input) in output.iter_mut().zip(&buffers[input]) {
+                    *value += input;
+                }
+            }
+            Function::ADSR(a, d, s, r, _) => {
+                for (i, value) in output.iter_mut().enumerate() {
+                    since_event += delta_time;
+                    let amp = if sounding {
+                        ads(buffers[a][i], buffers[d][i], buffers[s][i], since_event)
+                    } else {
+                        sr(buffers[s][i], buffers[r][i], since_event)
+                    };
+                    *value *= amp;
+                }
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
   pub struct DAGVoice {
       amp: f64,
       pub initial_state: Vec<f64>,
       state: Vec<f64>,
       functions: Vec<Function>,
+      velocity: f64,
       since_event: f64,
       since_onset: f64,
       sounding: bool,
+      // Per slot sample buffers for `process`.
+      #[serde(skip)]
+      buffers: Vec<Vec<f64>>,
   }
 
 impl DAGVoice {
@@ -214,10 +446,93 @@ impl DAGVoice {
             initial_state: state.clone(),
             state,
             functions,
+            velocity: 1.0,
             since_event: 100.0,
             since_onset: 100.0,
             sounding: false,
+            buffers: vec![],
+        }
+    }
+
+    /// A copy whose initial state values are each scaled by a random factor
+    /// between `1 - amount` and `1 + amount`. An `amount` of zero or less
+    /// leaves them as they are.
+    pub fn mutate<R: Rng>(&self, rng: &mut R, amount: f64) -> DAGVoice {
+        let mut child = self.clone();
+        if amount > 0.0 {
+            for value in &mut child.initial_state {
+                *value *= rng.gen_range(1.0 - amount, 1.0 + amount);
+            }
+        }
+        child.state = child.initial_state.clone();
+        child
+    }
+
+    fn apply_parameters(&mut self, note: &NoteOn) {
+        for (index, value) in &note.parameters {
+            if let Some(slot) = self.state.get_mut(*index) {
+                *slot = *value;
+            }
+        }
+    }
+
+    fn release_time(&self) -> Option<f64> {
+        let mut release = None;
+        for f in &self.functions {
+            if let Function::ADSR(_, _, _, r, _) = f {
+                release = Some(self.state[*r].max(release.unwrap_or(0.0)));
+            }
+        }
+        release
+    }
+
+    // Whether the output is zero whenever every ADSR is, that is whether each
+    // path to it from a slot that may be nonzero passes through an ADSR.
+    fn gated(&self) -> bool {
+        let written: Vec<usize> = self.functions.iter().map(|f| f.output()).collect();
+        let mut zero: Vec<bool> = self
+            .state
+            .iter()
+            .enumerate()
+            .map(|(slot, value)| *value == 0.0 && !written.contains(&slot))
+            .collect();
+        for f in &self.functions {
+            zero[f.output()] = match *f {
+                Function::Sin(..) => false,
+                Function::Copy(input, _) => zero[input],
+                Function::Multiply(input, output) => zero[input] || zero[output],
+                Function::Scale(_, output) => zero[output],
+                Function::Add(input, output) => zero[input] && zero[output],
+                Function::ADSR(..) => true,
+            };
         }
+        zero[self.state.len() - 1]
+    }
+
+    // Which slots carry their value from one sample to the next, being read
+    // by the function that first updates them. `None` if such a slot is read
+    // before that update or updated again, since then the graph can't be
+    // evaluated one function at a time.
+    fn carried(&self) -> Option<Vec<bool>> {
+        let mut read = vec![false; self.state.len()];
+        let mut written = vec![false; self.state.len()];
+        let mut carried = vec![false; self.state.len()];
+        for f in &self.functions {
+            let output = f.output();
+            let inputs = f.inputs();
+            if carried[output] || (read[output] && !written[output]) {
+                return None;
+            }
+            if !written[output] && (f.in_place() || inputs.contains(&output)) {
+                carried[output] = true;
+            }
+            for input in inputs {
+                read[input] = true;
+            }
+            read[output] |= f.in_place();
+            written[output] = true;
+        }
+        Some(carried)
     }
 }
 impl Voice for DAGVoice {
@@ -225,47 +540,122 @@ impl Voice for DAGVoice {
         self.since_event += delta_time;
         self.since_onset += delta_time;
         for f in &self.functions {
-            match f {
-                Function::Sin(freq, phase, output) => {
-                    let freq = self.state[*freq];
-                    let phase = self.state[*phase];
-                    self.state[*output] = (freq * (self.since_onset + phase) * 2.0 * PI).sin();
-                },
-                Function::Multiply(input, output) => {
-                    self.state[*output] *= self.state[*input];
-                },
-                Function::Scale(input, output) => {
-                    self.state[*output] *= 1.0 + self.state[*input];
-                },
-                Function::Add(input, output) => {
-                    self.state[*output] += self.state[*input];
-                },
-                Function::Copy(input, output) => {
-                    self.state[*output] = self.state[*input];
-                },
-                Function::ADSR(a, d, s, r, output) => {
-                    let a = self.state[*a];
-                    let d = self.state[*d];
-                    let s = self.state[*s];
-                    let r = self.state[*r];
-                    let amp = if self.sounding {
-                        ads(a, d, s, self.since_event)
-                    } else {
-                        sr(s, r, self.since_event)
-                    };
-                    self.state[*output] *= amp;
-                },
+            let state = &self.state;
+            let value = f.apply(
+                |slot| state[slot],
+                self.since_onset,
+                self.since_event,
+                self.sounding,
+            );
+            self.state[f.output()] = value;
+        }
+        self.state[self.state.len() - 1] * self.amp * self.velocity
+    }
+
+    /// Evaluates each function over the whole of `out` in turn. Graphs where
+    /// a slot carried from one sample to the next is read before it is
+    /// updated, or updated twice, are evaluated a sample at a time.
+    fn process(&mut self, delta_time: f64, out: &mut [f64]) {
+        let released = match self.release_time() {
+            Some(release) => !self.sounding && self.since_event > release,
+            None => false,
+        };
+        if released && self.gated() {
+            for sample in out.iter_mut() {
+                self.since_event += delta_time;
+                self.since_onset += delta_time;
+                *sample = 0.0;
             }
+            return;
+        }
+        let carried = match self.carried() {
+            Some(carried) => carried,
+            None => {
+                for sample in out.iter_mut() {
+                    *sample = self.sample(delta_time);
+                }
+                return;
+            }
+        };
+
+        let frames = out.len();
+        self.buffers.resize(self.state.len(), vec![]);
+        for (buffer, value) in self.buffers.iter_mut().zip(&self.state) {
+            buffer.clear();
+            buffer.resize(frames, *value);
+        }
+        for f in &self.functions {
+            let output = f.output();
+            if !carried[output] && !f.inputs().contains(&output) {
+                let mut buffer = std::mem::take(&mut self.buffers[output]);
+                f.apply_block(
+                    &mut buffer,
+                    &self.buffers,
+                    delta_time,
+                    self.since_onset,
+                    self.since_event,
+                    self.sounding,
+                );
+                self.buffers[output] = buffer;
+                continue;
+            }
+            // Slots carried between samples, and outputs also read as inputs,
+            // go a sample at a time.
+            let mut previous = self.state[output];
+            let mut since_event = self.since_event;
+            let mut since_onset = self.since_onset;
+            for i in 0..frames {
+                since_event += delta_time;
+                since_onset += delta_time;
+                if carried[output] {
+                    self.buffers[output][i] = previous;
+                }
+                let buffers = &self.buffers;
+                previous = f.apply(
+                    |slot| buffers[slot][i],
+                    since_onset,
+                    since_event,
+                    self.sounding,
+                );
+                self.buffers[output][i] = previous;
+            }
+        }
+        for _ in 0..frames {
+            self.since_event += delta_time;
+            self.since_onset += delta_time;
+        }
+        if frames > 0 {
+            for (value, buffer) in self.state.iter_mut().zip(&self.buffers) {
+                *value = buffer[frames - 1];
+            }
+        }
+        let output = &self.buffers[self.state.len() - 1];
+        for (sample, value) in out.iter_mut().zip(output) {
+            *sample = value * self.amp * self.velocity;
         }
-        self.state[self.state.len() - 1] * self.amp
     }
 
-    fn play_pitch(&mut self, pitch: &Pitch) {
+    /// `note.parameters` overwrite slots of the freshly reset state, so any
+    /// frequency, amplitude or envelope value can vary per note.
+    fn note_on(&mut self, note: &NoteOn) {
         self.state = self.initial_state.clone();
         self.since_event = 0.0;
         self.since_onset = 0.0;
         self.sounding = true;
-        self.state[0] = pitch.0 as f64;
+        self.velocity = note.velocity;
+        self.state[0] = note.pitch.0 as f64;
+        self.apply_parameters(note);
+    }
+
+    /// Changes pitch immediately, the function graph has no notion of glide.
+    fn legato(&mut self, note: &NoteOn, _glide_time: f64) {
+        if !self.sounding {
+            self.note_on(note);
+            return;
+        }
+        self.velocity = note.velocity;
+        self.state[0] = note.pitch.0 as f64;
+        self.apply_parameters(note);
     }
 
     fn stop(&mut self) {
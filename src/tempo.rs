@@ -0,0 +1,274 @@
+use super::synth::Note;
+use super::Pitch;
+
+/// A tempo in quarter notes per minute that takes effect at `beat`. When
+/// `ramp` is set the tempo changes linearly, beat by beat, from the previous
+/// change to this one instead of jumping at `beat`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TempoChange {
+    pub beat: f64,
+    pub bpm: f64,
+    pub ramp: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl TimeSignature {
+    /// Panics if either number is zero.
+    pub fn new(numerator: u32, denominator: u32) -> TimeSignature {
+        assert!(
+            numerator > 0 && denominator > 0,
+            "a time signature needs a beat and a beat length"
+        );
+        TimeSignature {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// Length of one beat of this signature in quarter notes.
+    pub fn beat_length(&self) -> f64 {
+        4.0 / f64::from(self.denominator)
+    }
+
+    pub fn bar_length(&self) -> f64 {
+        self.beat_length() * f64::from(self.numerator)
+    }
+}
+
+/// A point in musical time. Bars and beats count from zero and `beat` is in
+/// units of the time signature's denominator, so beat 1 of a 6/8 bar is the
+/// second eighth note. Ticks subdivide a beat into `TempoMap::ticks_per_beat`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub bar: u32,
+    pub beat: u32,
+    pub tick: u32,
+}
+
+impl Position {
+    pub fn new(bar: u32, beat: u32, tick: u32) -> Position {
+        Position { bar, beat, tick }
+    }
+}
+
+/// Converts between musical time, measured in quarter note beats from the
+/// start of the piece, and seconds.
+#[derive(Clone, Debug)]
+pub struct TempoMap {
+    tempos: Vec<TempoChange>,
+    // (bar, signature) in bar order, always starting at bar zero
+    signatures: Vec<(u32, TimeSignature)>,
+    pub ticks_per_beat: u32,
+}
+
+impl TempoMap {
+    pub fn new(bpm: f64) -> TempoMap {
+        TempoMap {
+            tempos: vec![TempoChange {
+                beat: 0.0,
+                bpm,
+                ramp: false,
+            }],
+            signatures: vec![(0, TimeSignature::new(4, 4))],
+            ticks_per_beat: 480,
+        }
+    }
+
+    /// Jumps to `bpm` at `beat`.
+    pub fn set_tempo(&mut self, beat: f64, bpm: f64) {
+        self.insert_tempo(TempoChange {
+            beat,
+            bpm,
+            ramp: false,
+        });
+    }
+
+    /// Ramps from the tempo at the previous change so that `bpm` is reached
+    /// at `beat`.
+    pub fn ramp_tempo(&mut self, beat: f64, bpm: f64) {
+        self.insert_tempo(TempoChange {
+            beat,
+            bpm,
+            ramp: true,
+        });
+    }
+
+    fn insert_tempo(&mut self, change: TempoChange) {
+        self.tempos.retain(|other| other.beat != change.beat);
+        let index = self
+            .tempos
+            .iter()
+            .position(|other| other.beat > change.beat)
+            .unwrap_or(self.tempos.len());
+        self.tempos.insert(index, change);
+    }
+
+    pub fn tempo_changes(&self) -> &[TempoChange] {
+        &self.tempos
+    }
+
+    /// Panics if either number of `signature` is zero, since bars of no
+    /// length would never end.
+    pub fn set_time_signature(&mut self, bar: u32, signature: TimeSignature) {
+        assert!(
+            signature.numerator > 0 && signature.denominator > 0,
+            "a time signature needs a beat and a beat length"
+        );
+        self.signatures.retain(|(other, _)| *other != bar);
+        let index = self
+            .signatures
+            .iter()
+            .position(|(other, _)| *other > bar)
+            .unwrap_or(self.signatures.len());
+        self.signatures.insert(index, (bar, signature));
+    }
+
+    pub fn time_signature(&self, bar: u32) -> TimeSignature {
+        self.signatures
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= bar)
+            .map(|(_, signature)| *signature)
+            .unwrap_or_else(|| TimeSignature::new(4, 4))
+    }
+
+    /// Tempo in effect at `beat`, following any ramp in progress.
+    pub fn bpm_at(&self, beat: f64) -> f64 {
+        let (segment, next) = self.segment(beat);
+        match next {
+            Some(next) if next.ramp => {
+                let progress = (beat - segment.beat) / (next.beat - segment.beat);
+                segment.bpm + (next.bpm - segment.bpm) * progress
+            }
+            _ => segment.bpm,
+        }
+    }
+
+    pub fn seconds_at(&self, beat: f64) -> f64 {
+        let mut seconds = 0.0;
+        for (i, change) in self.tempos.iter().enumerate() {
+            match self.tempos.get(i + 1) {
+                Some(next) if next.beat < beat => {
+                    seconds += segment_seconds(change, Some(next), next.beat)
+                }
+                next => return seconds + segment_seconds(change, next, beat),
+            }
+        }
+        seconds
+    }
+
+    pub fn beat_at(&self, seconds: f64) -> f64 {
+        let mut elapsed = 0.0;
+        for (i, change) in self.tempos.iter().enumerate() {
+            let next = self.tempos.get(i + 1);
+            if let Some(next) = next {
+                let length = segment_seconds(change, Some(next), next.beat);
+                if elapsed + length < seconds {
+                    elapsed += length;
+                    continue;
+                }
+            }
+            let remaining = seconds - elapsed;
+            return match next {
+                Some(next) if next.ramp && next.bpm != change.bpm => {
+                    let slope = (next.bpm - change.bpm) / (next.beat - change.beat);
+                    let bpm = change.bpm * (slope * remaining / 60.0).exp();
+                    change.beat + (bpm - change.bpm) / slope
+                }
+                _ => change.beat + remaining * change.bpm / 60.0,
+            };
+        }
+        unreachable!("a tempo map always has an initial tempo")
+    }
+
+    /// Quarter note beat at which `position` falls.
+    pub fn beat_of(&self, position: &Position) -> f64 {
+        let mut beat = 0.0;
+        for bar in 0..position.bar {
+            beat += self.time_signature(bar).bar_length();
+        }
+        let beat_length = self.time_signature(position.bar).beat_length();
+        let ticks = f64::from(position.tick) / f64::from(self.ticks_per_beat);
+        beat + (f64::from(position.beat) + ticks) * beat_length
+    }
+
+    /// The position of `beat`, with ticks rounded to the nearest whole tick.
+    pub fn position_of(&self, beat: f64) -> Position {
+        let mut bar = 0;
+        let mut bar_start = 0.0;
+        loop {
+            let length = self.time_signature(bar).bar_length();
+            if bar_start + length > beat {
+                break;
+            }
+            bar_start += length;
+            bar += 1;
+        }
+        let beat_length = self.time_signature(bar).beat_length();
+        let ticks = ((beat - bar_start) / beat_length * f64::from(self.ticks_per_beat)).round();
+        let ticks = ticks as u32;
+        Position {
+            bar,
+            beat: ticks / self.ticks_per_beat,
+            tick: ticks % self.ticks_per_beat,
+        }
+    }
+
+    pub fn seconds_of(&self, position: &Position) -> f64 {
+        self.seconds_at(self.beat_of(position))
+    }
+
+    fn segment(&self, beat: f64) -> (&TempoChange, Option<&TempoChange>) {
+        let index = self
+            .tempos
+            .iter()
+            .rposition(|change| change.beat <= beat)
+            .unwrap_or(0);
+        (&self.tempos[index], self.tempos.get(index + 1))
+    }
+}
+
+// Seconds from `change.beat` to `end`, where `end` is no later than the next
+// change.
+fn segment_seconds(change: &TempoChange, next: Option<&TempoChange>, end: f64) -> f64 {
+    let beats = end - change.beat;
+    match next {
+        Some(next) if next.ramp && next.bpm != change.bpm => {
+            let slope = (next.bpm - change.bpm) / (next.beat - change.beat);
+            let bpm = change.bpm + slope * beats;
+            60.0 / slope * (bpm / change.bpm).ln()
+        }
+        _ => beats * 60.0 / change.bpm,
+    }
+}
+
+/// A `Note` whose onset and duration are in quarter note beats rather than
+/// seconds.
+#[derive(Clone, Debug)]
+pub struct BeatNote {
+    pub instrument: usize,
+    pub pitch: Pitch,
+    pub onset: f64,
+    pub duration: f64,
+    pub amplitude: f64,
+    pub parameters: Vec<(usize, f64)>,
+}
+
+impl BeatNote {
+    pub fn to_note(&self, tempo: &TempoMap) -> Note {
+        let onset = tempo.seconds_at(self.onset);
+        Note {
+            instrument: self.instrument,
+            pitch: self.pitch,
+            onset,
+            duration: tempo.seconds_at(self.onset + self.duration) - onset,
+            amplitude: self.amplitude,
+            parameters: self.parameters.clone(),
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use super::{Pitch, CONCERT_A};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, reason: &'static str },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Parse { line, reason } => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// Maps MIDI keys onto the degrees of a `Tuning`, following the layout of a
+/// Scala `.kbm` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyboard {
+    pub first_key: i32,
+    pub last_key: i32,
+    /// Key that sounds degree zero.
+    pub middle_key: i32,
+    pub reference_key: i32,
+    pub reference_frequency: f64,
+    /// Degree that the mapping pattern repeats at. Unused by linear mappings.
+    pub octave_degree: usize,
+    /// Degree for each key of the repeating pattern that starts at
+    /// `middle_key`, `None` leaving the key silent. An empty mapping assigns
+    /// consecutive degrees to consecutive keys.
+    pub mapping: Vec<Option<usize>>,
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard {
+            first_key: 0,
+            last_key: 127,
+            middle_key: 60,
+            reference_key: 69,
+            reference_frequency: CONCERT_A,
+            octave_degree: 0,
+            mapping: vec![],
+        }
+    }
+}
+
+impl Keyboard {
+    pub fn read_kbm<P: AsRef<Path>>(path: P) -> Result<Keyboard, Error> {
+        Keyboard::parse_kbm(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse_kbm(source: &str) -> Result<Keyboard, Error> {
+        let mut lines = scala_lines(source);
+        let mut next = |reason| lines.next().ok_or(Error::Parse { line: 0, reason });
+        let (line, size) = next("missing map size")?;
+        let size: usize = parse_field(line, size, "invalid map size")?;
+        let mut fields = [0i32; 4];
+        for (field, reason) in fields.iter_mut().zip(&[
+            "invalid first key",
+            "invalid last key",
+            "invalid middle key",
+            "invalid reference key",
+        ]) {
+            let (line, value) = next("missing keyboard field")?;
+            *field = parse_field(line, value, reason)?;
+        }
+        let (line, frequency) = next("missing reference frequency")?;
+        let reference_frequency = parse_field(line, frequency, "invalid reference frequency")?;
+        let (line, octave) = next("missing octave degree")?;
+        let octave_degree = parse_field(line, octave, "invalid octave degree")?;
+        let mut mapping = Vec::with_capacity(size);
+        for _ in 0..size {
+            let (line, degree) = next("missing key mapping")?;
+            if degree.starts_with('x') {
+                mapping.push(None);
+            } else {
+                mapping.push(Some(parse_field(line, degree, "invalid key mapping")?));
+            }
+        }
+        Ok(Keyboard {
+            first_key: fields[0],
+            last_key: fields[1],
+            middle_key: fields[2],
+            reference_key: fields[3],
+            reference_frequency,
+            octave_degree,
+            mapping,
+        })
+    }
+}
+
+/// A tuning system: the pitches of one period, typically an octave, given in
+/// cents above the tonic, together with a keyboard mapping for MIDI keys.
+#[derive(Clone, Debug)]
+pub struct Tuning {
+    pub description: String,
+    // Cents of degrees 1 to n, where degree n is the period. Degree zero is
+    // the tonic and is implicit, as in Scala files.
+    cents: Vec<f64>,
+    pub keyboard: Keyboard,
+}
+
+impl Tuning {
+    /// A tuning from the cents of each degree above the tonic, the last of
+    /// which is the period.
+    pub fn from_cents(cents: &[f64]) -> Tuning {
+        assert!(!cents.is_empty(), "a tuning needs at least one degree");
+        Tuning {
+            description: String::new(),
+            cents: cents.to_vec(),
+            keyboard: Keyboard::default(),
+        }
+    }
+
+    /// A tuning from frequency ratios above the tonic, the last of which is
+    /// the period.
+    pub fn from_ratios(ratios: &[f64]) -> Tuning {
+        let cents: Vec<f64> = ratios.iter().map(|ratio| ratio_to_cents(*ratio)).collect();
+        Tuning::from_cents(&cents)
+    }
+
+    /// `divisions` equal steps to the octave. Panics if `divisions` is zero.
+    pub fn equal(divisions: u32) -> Tuning {
+        Tuning::equal_division(divisions, 2.0)
+    }
+
+    /// `divisions` equal steps to a period of `period_ratio`, such as the
+    /// thirteen steps to the tritave of the Bohlen-Pierce scale. Panics if
+    /// `divisions` is zero.
+    pub fn equal_division(divisions: u32, period_ratio: f64) -> Tuning {
+        assert!(divisions > 0, "an equal tuning needs at least one division");
+        let step = ratio_to_cents(period_ratio) / f64::from(divisions);
+        let cents: Vec<f64> = (1..=divisions).map(|i| step * f64::from(i)).collect();
+        let mut tuning = Tuning::from_cents(&cents);
+        tuning.description = format!("{} equal divisions of {}", divisions, period_ratio);
+        tuning
+    }
+
+    /// Twelve notes from a chain of fifths of `fifth` cents running from the
+    /// minor third to the augmented fifth above the tonic.
+    pub fn regular_temperament(fifth: f64) -> Tuning {
+        let mut cents: Vec<f64> = (-3..9)
+            .map(|i| (f64::from(i) * fifth).rem_euclid(1200.0))
+            .filter(|cents| *cents != 0.0)
+            .collect();
+        cents.push(1200.0);
+        cents.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Tuning::from_cents(&cents)
+    }
+
+    pub fn pythagorean() -> Tuning {
+        let mut tuning = Tuning::regular_temperament(ratio_to_cents(3.0 / 2.0));
+        tuning.description = "Pythagorean".to_string();
+        tuning
+    }
+
+    pub fn quarter_comma_meantone() -> Tuning {
+        let mut tuning = Tuning::regular_temperament(ratio_to_cents(5.0f64.powf(0.25)));
+        tuning.description = "Quarter-comma meantone".to_string();
+        tuning
+    }
+
+    /// Five-limit just intonation.
+    pub fn just_intonation() -> Tuning {
+        let mut tuning = Tuning::from_ratios(&[
+            16.0 / 15.0,
+            9.0 / 8.0,
+            6.0 / 5.0,
+            5.0 / 4.0,
+            4.0 / 3.0,
+            45.0 / 32.0,
+            3.0 / 2.0,
+            8.0 / 5.0,
+            5.0 / 3.0,
+            9.0 / 5.0,
+            15.0 / 8.0,
+            2.0,
+        ]);
+        tuning.description = "5-limit just intonation".to_string();
+        tuning
+    }
+
+    pub fn werckmeister_iii() -> Tuning {
+        Tuning::well_temperament(
+            "Werckmeister III",
+            [
+                90.225, 192.18, 294.135, 390.225, 498.045, 588.27, 696.09, 792.18, 888.27, 996.09,
+                1092.18,
+            ],
+        )
+    }
+
+    pub fn kirnberger_iii() -> Tuning {
+        Tuning::well_temperament(
+            "Kirnberger III",
+            [
+                90.225, 193.157, 294.135, 386.314, 498.045, 590.224, 696.578, 792.18, 889.735,
+                996.09, 1088.269,
+            ],
+        )
+    }
+
+    pub fn vallotti() -> Tuning {
+        Tuning::well_temperament(
+            "Vallotti",
+            [
+                94.135, 196.09, 298.045, 392.18, 501.955, 592.18, 698.045, 796.09, 894.135, 1000.0,
+                1090.225,
+            ],
+        )
+    }
+
+    fn well_temperament(description: &str, cents: [f64; 11]) -> Tuning {
+        let mut cents = cents.to_vec();
+        cents.push(1200.0);
+        let mut tuning = Tuning::from_cents(&cents);
+        tuning.description = description.to_string();
+        tuning
+    }
+
+    pub fn read_scl<P: AsRef<Path>>(path: P) -> Result<Tuning, Error> {
+        Tuning::parse_scl(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses the Scala `.scl` format. Pitches containing a `.` are cents and
+    /// anything else is a ratio such as `3/2` or `2`.
+    pub fn parse_scl(source: &str) -> Result<Tuning, Error> {
+        // The description may legitimately be blank so it can't go through
+        // scala_lines, which only yields lines with content.
+        let mut lines = source
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.starts_with('!'));
+        let description = lines
+            .next()
+            .map(|(_, line)| line.trim().to_string())
+            .ok_or(Error::Parse {
+                line: 0,
+                reason: "missing description",
+            })?;
+        let mut lines = lines
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+        let (line, count) = lines.next().ok_or(Error::Parse {
+            line: 0,
+            reason: "missing note count",
+        })?;
+        let count: usize = parse_field(line, first_word(count), "invalid note count")?;
+        if count == 0 {
+            return Err(Error::Parse {
+                line,
+                reason: "scale has no notes",
+            });
+        }
+        let mut cents = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (line, pitch) = lines.next().ok_or(Error::Parse {
+                line: 0,
+                reason: "fewer pitches than the note count",
+            })?;
+            cents.push(parse_scl_pitch(line, first_word(pitch))?);
+        }
+        let mut tuning = Tuning::from_cents(&cents);
+        tuning.description = description;
+        Ok(tuning)
+    }
+
+    pub fn with_keyboard(mut self, keyboard: Keyboard) -> Tuning {
+        self.keyboard = keyboard;
+        self
+    }
+
+    /// Number of degrees in one period.
+    pub fn len(&self) -> usize {
+        self.cents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cents.is_empty()
+    }
+
+    /// Size of the period in cents.
+    pub fn period(&self) -> f64 {
+        self.cents[self.cents.len() - 1]
+    }
+
+    /// Cents of `degree` above the tonic. Degrees outside the first period
+    /// repeat at the period, so negative degrees fall below the tonic.
+    pub fn cents(&self, degree: i32) -> f64 {
+        let len = self.cents.len() as i32;
+        let periods = degree.div_euclid(len);
+        let step = degree.rem_euclid(len);
+        let within = if step == 0 {
+            0.0
+        } else {
+            self.cents[step as usize - 1]
+        };
+        f64::from(periods) * self.period() + within
+    }
+
+    pub fn ratio(&self, degree: i32) -> f64 {
+        2.0f64.powf(self.cents(degree) / 1200.0)
+    }
+
+    pub fn pitch(&self, tonic: &Pitch, degree: i32) -> Pitch {
+        Pitch((f64::from(tonic.0) * self.ratio(degree)) as f32)
+    }
+
+    /// The pitch this tuning's keyboard mapping assigns to a MIDI key, or
+    /// `None` for keys that are out of range or unmapped.
+    pub fn key_pitch(&self, key: i32) -> Option<Pitch> {
+        let keyboard = &self.keyboard;
+        if key < keyboard.first_key || key > keyboard.last_key {
+            return None;
+        }
+        let cents = self.key_cents(key)?;
+        let reference = self.key_cents(keyboard.reference_key).unwrap_or(0.0);
+        let tonic = keyboard.reference_frequency / 2.0f64.powf(reference / 1200.0);
+        Some(Pitch((tonic * 2.0f64.powf(cents / 1200.0)) as f32))
+    }
+
+    // Cents above the middle key.
+    fn key_cents(&self, key: i32) -> Option<f64> {
+        let keyboard = &self.keyboard;
+        let offset = key - keyboard.middle_key;
+        if keyboard.mapping.is_empty() {
+            return Some(self.cents(offset));
+        }
+        let size = keyboard.mapping.len() as i32;
+        let degree = keyboard.mapping[offset.rem_euclid(size) as usize]?;
+        let octave = self.cents(keyboard.octave_degree as i32);
+        Some(self.cents(degree as i32) + f64::from(offset.div_euclid(size)) * octave)
+    }
+}
+
+pub fn ratio_to_cents(ratio: f64) -> f64 {
+    1200.0 * ratio.log2()
+}
+
+// Non-comment, non-blank lines with their one-based line numbers.
+fn scala_lines(source: &str) -> impl Iterator<Item = (usize, &str)> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('!'))
+}
+
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+fn parse_field<T: std::str::FromStr>(
+    line: usize,
+    field: &str,
+    reason: &'static str,
+) -> Result<T, Error> {
+    first_word(field)
+        .parse()
+        .map_err(|_| Error::Parse { line, reason })
+}
+
+fn parse_scl_pitch(line: usize, pitch: &str) -> Result<f64, Error> {
+    let reason = "invalid pitch";
+    if pitch.contains('.') {
+        return parse_field(line, pitch, reason);
+    }
+    let mut parts = pitch.splitn(2, '/');
+    let numerator: f64 = parse_field(line, parts.next().unwrap_or(""), reason)?;
+    let denominator: f64 = match parts.next() {
+        Some(denominator) => parse_field(line, denominator, reason)?,
+        None => 1.0,
+    };
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return Err(Error::Parse { line, reason });
+    }
+    Ok(ratio_to_cents(numerator / denominator))
+}
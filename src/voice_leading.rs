@@ -0,0 +1,401 @@
+use std::collections::HashSet;
+
+use super::synth::Note;
+use super::{Chord, Pitch};
+
+// Pitches closer than this in cents, ignoring octaves, are the same chord
+// tone.
+const CLASS_TOLERANCE: f64 = 1.0;
+// How many of the best voicings of each chord `realise` falls back on when
+// a later chord can't follow.
+const ALTERNATIVES: usize = 4;
+// Dead ends `realise` backs out of before giving up on a progression.
+const BUDGET: usize = 20_000;
+
+/// Where each voice moves from one chord to the next.
+#[derive(Clone, Debug)]
+pub struct VoiceLeading {
+    /// The (from, to) pitches of each voice, lowest voice first.
+    pub voices: Vec<(Pitch, Pitch)>,
+    /// Total motion of all voices in cents.
+    pub distance: f64,
+}
+
+impl VoiceLeading {
+    /// The voice leading from `from` to `to` with the least total motion,
+    /// each voice of `from` moving to the nearest octave of some pitch of
+    /// `to` and every pitch of `to` being used when there are enough voices.
+    /// Returns `None` when `to` is empty and there are voices to move.
+    pub fn minimal(from: &Chord, to: &Chord) -> Option<VoiceLeading> {
+        VoiceLeader::default().lead(from, to)
+    }
+
+    pub fn semitones(&self) -> f64 {
+        self.distance / 100.0
+    }
+
+    /// The chord the voices arrive at, in voice order.
+    pub fn target(&self) -> Chord {
+        Chord(self.voices.iter().map(|(_, to)| *to).collect())
+    }
+}
+
+/// Rules for moving voices between chords. Voices are numbered from the
+/// lowest up and a chord's pitches are taken as its voices in that order.
+#[derive(Clone, Debug)]
+pub struct VoiceLeader {
+    /// Lowest and highest pitch of each voice. Voices beyond the end of the
+    /// list may go anywhere.
+    pub ranges: Vec<(Pitch, Pitch)>,
+    pub allow_parallel_fifths: bool,
+    /// Covers parallel unisons as well as octaves.
+    pub allow_parallel_octaves: bool,
+    pub allow_crossing: bool,
+    /// The largest leap in cents any voice may make.
+    pub max_leap: Option<f64>,
+    /// The widest gap in cents allowed between neighbouring voices above
+    /// the lowest.
+    pub max_spacing: Option<f64>,
+    /// Put the lowest pitch of each chord in the lowest voice.
+    pub keep_bass: bool,
+}
+
+impl Default for VoiceLeader {
+    fn default() -> VoiceLeader {
+        VoiceLeader {
+            ranges: vec![],
+            allow_parallel_fifths: true,
+            allow_parallel_octaves: true,
+            allow_crossing: true,
+            max_leap: None,
+            max_spacing: None,
+            keep_bass: false,
+        }
+    }
+}
+
+impl VoiceLeader {
+    /// Four part chorale rules: bass, tenor, alto and soprano in their usual
+    /// ranges, no parallel fifths or octaves, no crossing, leaps of at most
+    /// an octave, upper voices within an octave of each other and the bass
+    /// of each chord in the bass.
+    pub fn satb() -> VoiceLeader {
+        let range = |low: &str, high: &str| (low.parse().unwrap(), high.parse().unwrap());
+        VoiceLeader {
+            ranges: vec![
+                range("E2", "C4"),
+                range("C3", "G4"),
+                range("G3", "C5"),
+                range("C4", "G5"),
+            ],
+            allow_parallel_fifths: false,
+            allow_parallel_octaves: false,
+            allow_crossing: false,
+            max_leap: Some(1200.0),
+            max_spacing: Some(1200.0),
+            keep_bass: true,
+        }
+    }
+
+    /// The voice leading from `from` to `to` with the least total motion
+    /// that keeps to the rules, or `None` if there isn't one.
+    pub fn lead(&self, from: &Chord, to: &Chord) -> Option<VoiceLeading> {
+        self.search(&from.0, to, true, 1).into_iter().next()
+    }
+
+    // Like `lead` but keeping up to `count` voice leadings, cheapest first.
+    pub(crate) fn leads(&self, from: &Chord, to: &Chord, count: usize) -> Vec<VoiceLeading> {
+        self.search(&from.0, to, true, count)
+    }
+
+    /// A voicing of `chord` with as many voices as there are ranges, placing
+    /// each voice as near the middle of its range as the rules allow.
+    pub fn voice(&self, chord: &Chord) -> Option<Chord> {
+        self.voicings(chord, 1)
+            .into_iter()
+            .next()
+            .map(|leading| leading.target())
+    }
+
+    /// Voices a whole progression, starting from `voice` for the first chord
+    /// and leading each chord to the next. A few of the best voicings of each
+    /// chord are kept in reserve in case a later chord can't follow the best.
+    /// Without ranges the first chord is used as it stands. Returns `None`
+    /// when the search runs out of ways on, or gives up after backing out of
+    /// a fixed number of dead ends.
+    pub fn realise(&self, chords: &[Chord]) -> Option<Vec<Chord>> {
+        let first = match chords.first() {
+            Some(first) => first,
+            None => return Some(vec![]),
+        };
+        let mut starts = if self.ranges.is_empty() {
+            vec![first.sorted()]
+        } else {
+            self.voicings(first, ALTERNATIVES)
+                .into_iter()
+                .map(|leading| leading.target())
+                .collect()
+        };
+        starts.reverse();
+        // The voicings still to try for each chord, best last, and the index
+        // and pitches of voicings already found to lead nowhere.
+        let mut untried = vec![starts];
+        let mut voiced: Vec<Chord> = vec![];
+        let mut dead_ends = HashSet::new();
+        let mut backtracks = 0;
+        while let Some(voicings) = untried.last_mut() {
+            let voicing = match voicings.pop() {
+                Some(voicing) => voicing,
+                None => {
+                    untried.pop();
+                    if let Some(dead_end) = voiced.pop() {
+                        dead_ends.insert((voiced.len(), dead_end.0));
+                        backtracks += 1;
+                        if backtracks > BUDGET {
+                            return None;
+                        }
+                    }
+                    continue;
+                }
+            };
+            if dead_ends.contains(&(voiced.len(), voicing.0.clone())) {
+                continue;
+            }
+            voiced.push(voicing);
+            let next = match chords.get(voiced.len()) {
+                Some(next) => next,
+                None => return Some(voiced),
+            };
+            let mut leads: Vec<Chord> = self
+                .leads(voiced.last().unwrap(), next, ALTERNATIVES)
+                .into_iter()
+                .map(|leading| leading.target())
+                .collect();
+            leads.reverse();
+            untried.push(leads);
+        }
+        None
+    }
+
+    // Voicings of `chord` scored by their distance from the middle of each
+    // range.
+    pub(crate) fn voicings(&self, chord: &Chord, count: usize) -> Vec<VoiceLeading> {
+        let middles: Vec<Pitch> = self
+            .ranges
+            .iter()
+            .map(|(low, high)| low.offset_cents(low.cents_to(high) / 2.0))
+            .collect();
+        self.search(&middles, chord, false, count)
+    }
+
+    // The `count` cheapest ways of moving the voices at `from` onto the
+    // pitches of `to`, cheapest first. Rules about motion are only checked
+    // when `moving`.
+    fn search(&self, from: &[Pitch], to: &Chord, moving: bool, count: usize) -> Vec<VoiceLeading> {
+        let mut classes: Vec<Pitch> = vec![];
+        for pitch in to.sorted().0 {
+            if !classes
+                .iter()
+                .any(|class| class.same_class(&pitch, CLASS_TOLERANCE))
+            {
+                classes.push(pitch);
+            }
+        }
+        let candidates: Vec<Vec<(Pitch, usize)>> = from
+            .iter()
+            .enumerate()
+            .map(|(voice, start)| {
+                let mut candidates = vec![];
+                for (class, pitch) in classes.iter().enumerate() {
+                    if self.keep_bass && voice == 0 && class != 0 {
+                        continue;
+                    }
+                    let octaves = match self.ranges.get(voice) {
+                        Some((low, high)) => octaves_between(*pitch, low, high),
+                        None => around(*pitch, start),
+                    };
+                    candidates.extend(
+                        octaves
+                            .into_iter()
+                            .filter(|to| {
+                                !moving
+                                    || self
+                                        .max_leap
+                                        .is_none_or(|leap| start.cents_to(to).abs() <= leap)
+                            })
+                            .map(|to| (to, class)),
+                    );
+                }
+                candidates.sort_by(|a, b| {
+                    let motion = |to: &Pitch| start.cents_to(to).abs();
+                    motion(&a.0).partial_cmp(&motion(&b.0)).unwrap()
+                });
+                candidates
+            })
+            .collect();
+
+        let mut search = Search {
+            leader: self,
+            from,
+            candidates: &candidates,
+            needed: classes.len().min(from.len()),
+            moving,
+            count,
+            chosen: vec![],
+            covered: vec![0; classes.len()],
+            found: vec![],
+        };
+        search.extend(0.0);
+        search.found
+    }
+
+    // Whether `to` can join the voices already `chosen` below it.
+    fn allows(&self, from: &[Pitch], chosen: &[Pitch], to: Pitch, moving: bool) -> bool {
+        let voice = chosen.len();
+        if let Some(below) = chosen.last() {
+            if !self.allow_crossing && to < *below {
+                return false;
+            }
+            if voice >= 2
+                && self
+                    .max_spacing
+                    .is_some_and(|spacing| below.cents_to(&to) > spacing)
+            {
+                return false;
+            }
+        }
+        if !moving {
+            return true;
+        }
+        chosen.iter().enumerate().all(|(other, other_to)| {
+            let before = from[other].cents_to(&from[voice]);
+            let after = other_to.cents_to(&to);
+            let direction = |from: &Pitch, to: &Pitch| from.cents_to(to).signum() as i32;
+            let parallel = from[voice] != to
+                && from[other] != *other_to
+                && direction(&from[voice], &to) == direction(&from[other], other_to);
+            let perfect = |cents: f64, class: f64| {
+                let reduced = cents.abs().rem_euclid(1200.0);
+                (reduced - class).abs() < 50.0 || (reduced - class - 1200.0).abs() < 50.0
+            };
+            !(parallel
+                && ((!self.allow_parallel_fifths
+                    && perfect(before, 700.0)
+                    && perfect(after, 700.0))
+                    || (!self.allow_parallel_octaves
+                        && perfect(before, 0.0)
+                        && perfect(after, 0.0))))
+        })
+    }
+}
+
+// Depth first search over the candidate pitches of each voice, keeping the
+// cheapest complete voice leadings found so far.
+struct Search<'a> {
+    leader: &'a VoiceLeader,
+    from: &'a [Pitch],
+    candidates: &'a [Vec<(Pitch, usize)>],
+    // How many different chord tones the voices must cover.
+    needed: usize,
+    moving: bool,
+    count: usize,
+    chosen: Vec<Pitch>,
+    covered: Vec<usize>,
+    found: Vec<VoiceLeading>,
+}
+
+impl<'a> Search<'a> {
+    fn extend(&mut self, distance: f64) {
+        if self.found.len() == self.count && distance >= self.found[self.count - 1].distance {
+            return;
+        }
+        let voice = self.chosen.len();
+        let covered = self.covered.iter().filter(|n| **n > 0).count();
+        if covered + (self.from.len() - voice) < self.needed {
+            return;
+        }
+        if voice == self.from.len() {
+            let leading = VoiceLeading {
+                voices: self
+                    .from
+                    .iter()
+                    .cloned()
+                    .zip(self.chosen.iter().cloned())
+                    .collect(),
+                distance,
+            };
+            let at = self
+                .found
+                .iter()
+                .position(|found| found.distance > distance)
+                .unwrap_or(self.found.len());
+            self.found.insert(at, leading);
+            self.found.truncate(self.count);
+            return;
+        }
+        for (to, class) in &self.candidates[voice] {
+            if !self
+                .leader
+                .allows(self.from, &self.chosen, *to, self.moving)
+            {
+                continue;
+            }
+            self.chosen.push(*to);
+            self.covered[*class] += 1;
+            self.extend(distance + self.from[voice].cents_to(to).abs());
+            self.covered[*class] -= 1;
+            self.chosen.pop();
+        }
+    }
+}
+
+// Every octave of `pitch` from `low` up to `high`.
+fn octaves_between(pitch: Pitch, low: &Pitch, high: &Pitch) -> Vec<Pitch> {
+    // Start from the octave at or below `low` so rounding can't skip the one
+    // that lies on it
+    let mut pitch = pitch.apply_interval(2.0f64.powf((pitch.cents_to(low) / 1200.0).floor()));
+    let mut octaves = vec![];
+    while pitch <= *high {
+        if pitch >= *low {
+            octaves.push(pitch);
+        }
+        pitch = pitch.apply_interval(2.0);
+    }
+    octaves
+}
+
+// The octaves of `pitch` just below and above `start`.
+fn around(pitch: Pitch, start: &Pitch) -> Vec<Pitch> {
+    let below = pitch.apply_interval(2.0f64.powf((pitch.cents_to(start) / 1200.0).floor()));
+    vec![below, below.apply_interval(2.0)]
+}
+
+/// Turns a voiced progression into notes, each chord lasting `duration`
+/// seconds and a voice holding its pitch rather than repeating it.
+pub fn notes(voicings: &[Chord], duration: f64, instrument: usize) -> Vec<Note> {
+    let mut notes: Vec<Note> = vec![];
+    let mut sounding: Vec<Option<usize>> = vec![];
+    for (i, chord) in voicings.iter().enumerate() {
+        sounding.resize(chord.0.len().max(sounding.len()), None);
+        for (voice, pitch) in chord.0.iter().enumerate() {
+            match sounding[voice] {
+                Some(held) if notes[held].pitch == *pitch => notes[held].duration += duration,
+                _ => {
+                    sounding[voice] = Some(notes.len());
+                    notes.push(Note {
+                        instrument,
+                        pitch: *pitch,
+                        onset: i as f64 * duration,
+                        duration,
+                        amplitude: 1.0,
+                        parameters: vec![],
+                    });
+                }
+            }
+        }
+        for held in sounding.iter_mut().skip(chord.0.len()) {
+            *held = None;
+        }
+    }
+    notes
+}